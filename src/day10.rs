@@ -171,7 +171,7 @@ impl FromIterator<char> for Grid {
 }
 
 impl Grid {
-    fn walk_from_start(&self) -> (Walker, Walker) {
+    fn walk_from_start(&self) -> (Walker<'_>, Walker<'_>) {
         // find the two starting positions adjacent to start_pos
         let mut walker1 = None;
         let mut walker2 = None;
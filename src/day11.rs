@@ -21,8 +21,15 @@ impl UnparsedGrid {
     fn new(grid: &[u8]) -> Self {
         // I don't know what to expect in part 2 but I suspect it will change
         // the way we do the expansion. So, just in doubt, I'm copying the input
-        // string and doing the expansion in the solver function
-        let grid = trim_input_eol(grid).to_vec().into_boxed_slice();
+        // string and doing the expansion in the solver function.
+        //
+        // Any CR is dropped while copying, so that CRLF input ends up with
+        // lines of exactly `width + 1` bytes like LF input does.
+        let grid: Box<[u8]> = trim_input_eol(grid)
+            .iter()
+            .copied()
+            .filter(|&c| c != b'\r')
+            .collect();
         let width = grid
             .split(|&c| c == b'\n')
             .next()
@@ -169,6 +176,63 @@ mod tests {
         assert_eq!(distance(3, 6), 17);
         assert_eq!(distance(8, 9), 5);
     }
+
+    #[test]
+    fn crlf_line_endings() {
+        let input = unindent::unindent_bytes(
+            b"
+            ...#......
+            .......#..
+            #.........
+            ..........
+            ......#...
+            .#........
+            .........#
+            ..........
+            .......#..
+            #...#.....
+            ",
+        );
+        let input_crlf = String::from_utf8(input.clone())
+            .unwrap()
+            .replace('\n', "\r\n")
+            .into_bytes();
+        let grid = UnparsedGrid::new(&input);
+        let grid_crlf = UnparsedGrid::new(&input_crlf);
+        assert_eq!(grid_crlf.width, grid.width);
+        assert_eq!(grid_crlf.height, grid.height);
+        assert_eq!(grid_crlf.expand(2), grid.expand(2));
+    }
+
+    #[test]
+    fn expand_by_factor_one() {
+        // an empty line counts as `expansion_factor` lines, so a factor of 1
+        // means no expansion at all
+        let input = unindent::unindent_bytes(
+            b"
+            #....
+            .....
+            ..#..
+            .....
+            ....#
+            ",
+        );
+        let grid = UnparsedGrid::new(&input);
+        let unexpanded: Vec<_> = grid
+            .unexpanded_positions()
+            .into_iter()
+            .map(|(row, col)| position(col, row))
+            .collect();
+        let positions = grid.expand(1);
+        assert_eq!(positions, unexpanded);
+        let distances = |positions: &[Position]| {
+            positions
+                .pairs()
+                .map(|(p1, p2)| p1.manhattan_distance(p2))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(distances(&positions), vec![4, 8, 4]);
+    }
 }
 
 example_tests! {
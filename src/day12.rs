@@ -60,8 +60,14 @@ impl FromStr for SpringRow {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (first, second) = s.trim().split_once(' ').ok_or(())?;
-        let pattern = first.chars().map(|c| c.try_into().unwrap()).collect();
-        let known_damaged = second.split(',').map(|s| s.parse().unwrap()).collect();
+        let pattern = first
+            .chars()
+            .map(Spring::try_from)
+            .collect::<Result<_, _>>()?;
+        let known_damaged = second
+            .split(',')
+            .map(|s| s.parse().map_err(|_| ()))
+            .collect::<Result<_, _>>()?;
         Ok(Self {
             pattern,
             known_damaged,
@@ -93,6 +99,37 @@ impl fmt::Display for DisplayRow<'_> {
     }
 }
 
+/// Display a concrete filling of a row, with the springs that contradict the
+/// known springs of the pattern wrapped in brackets.
+struct DisplayFilledRow<'a> {
+    pattern: &'a [Spring],
+    fill: &'a [Spring],
+}
+
+impl fmt::Display for DisplayFilledRow<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (&known, &filled) in self.pattern.iter().zip(self.fill) {
+            if known.matches(filled) {
+                write!(f, "{filled}")?;
+            } else {
+                write!(f, "[{filled}]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SpringRow {
+    #[cfg_attr(not(test), allow(unused))]
+    fn display_with_fill<'a>(&'a self, fill: &'a [Spring]) -> impl fmt::Display + 'a {
+        debug_assert_eq!(self.pattern.len(), fill.len());
+        DisplayFilledRow {
+            pattern: &self.pattern,
+            fill,
+        }
+    }
+}
+
 impl fmt::Display for SpringRow {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} {:?}", DisplayRow(&self.pattern), &self.known_damaged)
@@ -107,6 +144,10 @@ fn parse(input: &str) -> Vec<SpringRow> {
 /// A collection of solvers that seemed fun or interesting but turned out not to
 /// be good enough for the actual problem (especially part 2, for which it's
 /// unfeasible to explicitly enumerate all matching patterns).
+///
+/// The solvers themselves are only compiled with the `day12-reference-solvers`
+/// feature; the enumeration of arrangements is always available because it's
+/// used by [enumerate_matches].
 mod solving_the_bad_way {
     use super::*;
 
@@ -229,6 +270,11 @@ mod solving_the_bad_way {
         arrangements.filter(move |arr| arr.damaged() == total_damaged && arr.matches_row(row))
     }
 
+    pub(super) fn matching_fillings(row: &SpringRow) -> impl Iterator<Item = Vec<Spring>> + '_ {
+        matching_arrangements(row).map(|arr| arr.springs().collect())
+    }
+
+    #[cfg(feature = "day12-reference-solvers")]
     fn bits_from_stars_and_bars(size: usize, zeroes: &[usize], ones: &[usize]) -> u128 {
         let mut shift = size;
         let mut result = 0;
@@ -242,6 +288,8 @@ mod solving_the_bad_way {
         }
         result
     }
+
+    #[cfg(feature = "day12-reference-solvers")]
     fn enumerate_stars_and_bars(size: usize, constraints: &[usize], pattern: &[Spring]) -> usize {
         // stars and bars problem, or "allocate N balls in K buckets"
         let max_buckets = constraints.len();
@@ -249,12 +297,6 @@ mod solving_the_bad_way {
 
         let mut result = 0;
 
-        println!("enumerate_stars_and_bars({}, {:?})", size, constraints);
-        println!(
-            "binomial coefficient: {}",
-            binomial_coeffiecient(balls - 1, max_buckets - 1)
-        );
-
         #[derive(Debug, Clone)]
         struct StackItem {
             current: [usize; 128],
@@ -314,17 +356,20 @@ mod solving_the_bad_way {
         result
     }
 
-    #[allow(unused)]
+    #[cfg(feature = "day12-reference-solvers")]
+    #[cfg_attr(not(test), allow(unused))]
     pub fn solve_the_bad_way(row: &SpringRow) -> u64 {
         matching_arrangements(row).count() as u64
     }
 
-    #[allow(unused)]
+    #[cfg(feature = "day12-reference-solvers")]
+    #[cfg_attr(not(test), allow(unused))]
     pub fn solve_the_second_worst_way(row: &SpringRow) -> u64 {
         enumerate_stars_and_bars(row.pattern.len(), &row.known_damaged, &row.pattern) as u64
     }
 
-    #[allow(unused)]
+    #[cfg(feature = "day12-reference-solvers")]
+    #[cfg_attr(not(test), allow(unused))]
     pub(super) fn solve_depth_first(row: &SpringRow) -> u64 {
         // This is actually kinda similar to the solve_recursive() that I ended
         // up using, but in its current state it's unusable for part 2 because
@@ -482,12 +527,13 @@ mod solving_the_bad_way {
             const PATTERNS: usize = 1 << BITS;
             let arrangements: Vec<_> = EnumerateArrangments::new(BITS).collect();
             assert_eq!(arrangements.len(), PATTERNS);
-            for i in 0..PATTERNS {
-                assert_eq!(arrangements[i], Arrangement::from_bits(i as u128, BITS));
+            for (i, arrangement) in arrangements.iter().enumerate() {
+                assert_eq!(*arrangement, Arrangement::from_bits(i as u128, BITS));
             }
         }
 
         #[test]
+        #[cfg(feature = "day12-reference-solvers")]
         fn compare_solvers() {
             let input = include_str!("../input/2023/day12.txt");
             let rows = parse(input);
@@ -502,28 +548,109 @@ mod solving_the_bad_way {
         }
 
         #[test]
+        #[cfg(feature = "day12-reference-solvers")]
         fn depth_first_broken_case() {
             let row: SpringRow = "????????#????#?.# 1,2,3,2,1".parse().unwrap();
             let result = solve_depth_first(&row);
             assert_eq!(result, 38);
         }
+
+        #[test]
+        #[cfg(feature = "day12-reference-solvers")]
+        fn reference_solvers_match_depth_first() {
+            let rows = parse(&unindent::unindent(
+                "
+                ???.### 1,1,3
+                .??..??...?##. 1,1,3
+                ?#?#?#?#?#?#?#? 1,3,1,6
+                ????.#...#... 4,1,1
+                ????.######..#####. 1,6,5
+                ?###???????? 3,2,1
+                ",
+            ));
+            for row in &rows {
+                let expected = solve_depth_first(row);
+                assert_eq!(solve_the_bad_way(row), expected, "{row}");
+                assert_eq!(solve_the_second_worst_way(row), expected, "{row}");
+                assert_eq!(solve_recursive(row), expected, "{row}");
+            }
+        }
     }
 }
 
+/// Maximum pattern length accepted by [enumerate_matches].
+const MAX_ENUMERATED_LENGTH: usize = 20;
+
+/// Iterate over all the concrete fillings of a row that match it.
+///
+/// This explicitly goes through every possible filling of the pattern, so it's
+/// only meant for small rows: returns `None` for rows longer than
+/// [MAX_ENUMERATED_LENGTH] springs.
+#[cfg_attr(not(test), allow(unused))]
+fn enumerate_matches(row: &SpringRow) -> Option<impl Iterator<Item = Vec<Spring>> + '_> {
+    (row.pattern.len() <= MAX_ENUMERATED_LENGTH)
+        .then(|| solving_the_bad_way::matching_fillings(row))
+}
+
 fn is_valid(partial: &[Spring], pattern: &[Spring]) -> bool {
     partial.iter().zip(pattern).all(|(a, b)| a.matches(*b))
 }
 
+/// Memoization storage for [solve_partial], keyed by `(start, current_group)`.
+trait PartialCache {
+    fn get(&self, key: (usize, usize)) -> Option<u64>;
+    fn insert(&mut self, key: (usize, usize), value: u64);
+}
+
+impl PartialCache for HashMap<(usize, usize), u64> {
+    fn get(&self, key: (usize, usize)) -> Option<u64> {
+        HashMap::get(self, &key).copied()
+    }
+
+    fn insert(&mut self, key: (usize, usize), value: u64) {
+        HashMap::insert(self, key, value);
+    }
+}
+
+/// A [PartialCache] backed by a flat array with a slot for every possible key
+/// of a row.
+#[derive(Debug, Default)]
+struct DenseCache {
+    values: Vec<Option<u64>>,
+    groups: usize,
+}
+
+impl DenseCache {
+    /// Empty the cache and make room for all the keys of `row`.
+    fn reset(&mut self, row: &SpringRow) {
+        self.groups = row.known_damaged.len();
+        self.values.clear();
+        // `start` can be one past the end of the pattern, hence the +1
+        self.values
+            .resize((row.pattern.len() + 1) * self.groups, None);
+    }
+}
+
+impl PartialCache for DenseCache {
+    fn get(&self, (start, current_group): (usize, usize)) -> Option<u64> {
+        self.values[start * self.groups + current_group]
+    }
+
+    fn insert(&mut self, (start, current_group): (usize, usize), value: u64) {
+        self.values[start * self.groups + current_group] = Some(value);
+    }
+}
+
 fn solve_partial(
-    partial: &mut [Spring; 128],
+    partial: &mut [Spring],
     start: usize,
     current_group: usize,
     residual_damaged: usize,
-    cache: &mut HashMap<(usize, usize), u64>,
+    cache: &mut impl PartialCache,
     row: &SpringRow,
 ) -> u64 {
     let key = (start, current_group);
-    if let Some(&result) = cache.get(&key) {
+    if let Some(result) = cache.get(key) {
         return result;
     }
     let last_group = row.known_damaged.len() - 1;
@@ -581,37 +708,98 @@ fn solve_partial(
     result
 }
 
-fn solve_recursive(row: &SpringRow) -> u64 {
-    let mut cache = HashMap::new();
+/// Check that the damaged groups, with at least one operational spring between
+/// each of them, can fit in the row at all. Rows without any damaged group
+/// can't be parsed and are not supported by the solver either, so they are
+/// reported as unsatisfiable.
+fn is_satisfiable(row: &SpringRow) -> bool {
     let total_damaged: usize = row.known_damaged.iter().sum();
-    solve_partial(
-        &mut [Spring::Operational; 128],
-        0,
-        0,
-        total_damaged,
-        &mut cache,
-        row,
-    )
+    let separators = row.known_damaged.len().saturating_sub(1);
+    !row.known_damaged.is_empty() && total_damaged + separators <= row.pattern.len()
+}
+
+fn solve_recursive(row: &SpringRow) -> u64 {
+    Solver::default().solve(row)
+}
+
+/// Same as [solve_recursive], but keeps its buffers around so that solving
+/// many rows in a row doesn't allocate a new cache every time.
+///
+/// Cache entries are only valid for the row they were computed for, so the
+/// cache is cleared (but not deallocated) before each row.
+#[derive(Debug, Default)]
+struct Solver {
+    cache: DenseCache,
+    partial: Vec<Spring>,
+}
+
+impl Solver {
+    fn solve(&mut self, row: &SpringRow) -> u64 {
+        if !is_satisfiable(row) {
+            return 0;
+        }
+        self.cache.reset(row);
+        self.partial.clear();
+        self.partial.resize(row.pattern.len(), Spring::Operational);
+        let total_damaged: usize = row.known_damaged.iter().sum();
+        solve_partial(&mut self.partial, 0, 0, total_damaged, &mut self.cache, row)
+    }
+}
+
+/// Count the arrangements of damaged springs that match a single row.
+fn count_arrangements(row: &SpringRow) -> u64 {
+    solve_recursive(row)
+}
+
+/// Count the arrangements for a row after unfolding it `repeat` times.
+#[cfg_attr(not(test), allow(unused))]
+fn count_unfolded(row: &SpringRow, repeat: usize) -> u64 {
+    count_arrangements(&row.clone().repeat(repeat))
+}
+
+/// Count the arrangements of a row unfolded by each factor from 1 up to
+/// `max_factor` (included).
+#[cfg_attr(not(test), allow(unused))]
+fn arrangement_growth(row: &SpringRow, max_factor: usize) -> Vec<u64> {
+    let mut solver = Solver::default();
+    (1..=max_factor)
+        .map(|factor| solver.solve(&row.clone().repeat(factor)))
+        .collect()
 }
 
 #[aoc(day12, part1)]
 fn part1(input: &[SpringRow]) -> u64 {
-    input.iter().map(solve_recursive).sum()
+    input.iter().map(count_arrangements).sum()
+}
+
+/// Solve part 2 with rows unfolded `factor` times instead of 5.
+fn part2_with_factor(input: &[SpringRow], factor: usize) -> u64 {
+    let mut solver = Solver::default();
+    input
+        .iter()
+        .map(|row| solver.solve(&row.clone().repeat(factor)))
+        .sum()
 }
 
 #[aoc(day12, part2)]
 fn part2(input: &[SpringRow]) -> u64 {
-    let unfolded: Vec<_> = input.iter().map(|row| row.clone().repeat(5)).collect();
-    unfolded.iter().map(solve_recursive).sum()
+    part2_with_factor(input, 5)
 }
 
-fn binomial_coeffiecient(n: usize, k: usize) -> usize {
-    let mut result = 1;
-    for i in 0..k {
-        result *= n - i;
-        result /= i + 1;
-    }
-    result
+#[aoc(day12, part2, threaded)]
+fn part2_threaded(input: &[SpringRow]) -> u64 {
+    // rows are independent, so just split them in one chunk per thread
+    use std::thread;
+
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk_size = input.len().div_ceil(threads).max(1);
+    thread::scope(|s| {
+        let handles: Vec<_> = input
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || part2(chunk)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
 }
 
 #[cfg(test)]
@@ -655,6 +843,77 @@ mod tests {
         assert_eq!(solve("?###???????? 3,2,1"), 506250);
     }
 
+    #[test]
+    fn part2_factor_one_is_part1() {
+        let input = parse(&unindent::unindent(
+            "
+            ???.### 1,1,3
+            .??..??...?##. 1,1,3
+            ?#?#?#?#?#?#?#? 1,3,1,6
+            ????.#...#... 4,1,1
+            ????.######..#####. 1,6,5
+            ?###???????? 3,2,1
+            ",
+        ));
+        assert_eq!(part2_with_factor(&input, 1), part1(&input));
+        assert_eq!(part2_with_factor(&input, 5), part2(&input));
+    }
+
+    #[test]
+    fn unsatisfiable_rows() {
+        let row: SpringRow = "?? 5".parse().unwrap();
+        assert!(!is_satisfiable(&row));
+        assert_eq!(count_arrangements(&row), 0);
+        let row: SpringRow = "??? 2,1".parse().unwrap();
+        assert!(!is_satisfiable(&row));
+        assert_eq!(count_arrangements(&row), 0);
+        let row: SpringRow = "???? 2,1".parse().unwrap();
+        assert!(is_satisfiable(&row));
+        assert_eq!(count_arrangements(&row), 1);
+    }
+
+    #[test]
+    fn count_single_row() {
+        let row: SpringRow = "?###???????? 3,2,1".parse().unwrap();
+        assert_eq!(count_arrangements(&row), 10);
+        assert_eq!(count_unfolded(&row, 1), 10);
+        assert_eq!(count_unfolded(&row, 5), 506250);
+    }
+
+    #[test]
+    fn enumerate_small_row() {
+        use Spring::{Damaged as D, Operational as O};
+
+        let row: SpringRow = "???? 1,1".parse().unwrap();
+        let matches: Vec<_> = enumerate_matches(&row).unwrap().collect();
+        assert_eq!(
+            matches,
+            vec![vec![O, D, O, D], vec![D, O, O, D], vec![D, O, D, O]]
+        );
+    }
+
+    #[test]
+    fn enumerate_long_row() {
+        let longest: SpringRow = "????????????????.### 1,3".parse().unwrap();
+        assert_eq!(longest.pattern.len(), MAX_ENUMERATED_LENGTH);
+        assert_eq!(enumerate_matches(&longest).unwrap().count(), 16);
+
+        let too_long: SpringRow = "?????????????????.### 1,3".parse().unwrap();
+        assert!(enumerate_matches(&too_long).is_none());
+    }
+
+    #[test]
+    fn display_filled_row() {
+        use Spring::{Damaged as D, Operational as O};
+
+        let row: SpringRow = "?#?. 1".parse().unwrap();
+        let valid = row.display_with_fill(&[O, D, O, O]).to_string();
+        assert_eq!(valid, ".#..");
+        assert!(!valid.contains('['));
+        let invalid = row.display_with_fill(&[D, O, O, D]).to_string();
+        assert_eq!(invalid, "#[.].[#]");
+    }
+
     #[test]
     fn parse_row() {
         use Spring::{Damaged as D, Operational as O, Unknown as U};
@@ -668,6 +927,14 @@ mod tests {
         assert_eq!(&row.known_damaged, &[4, 1, 1]);
     }
 
+    #[test]
+    fn parse_invalid_row() {
+        assert!("???x 1,1".parse::<SpringRow>().is_err());
+        assert!("???? 1,x".parse::<SpringRow>().is_err());
+        assert!("???? 1,,1".parse::<SpringRow>().is_err());
+        assert!("????".parse::<SpringRow>().is_err());
+    }
+
     #[test]
     fn unfold_row() {
         let row: SpringRow = ".# 1".parse::<SpringRow>().unwrap().repeat(5);
@@ -684,18 +951,174 @@ mod tests {
         assert_eq!(row.known_damaged, already_unfolded_row.known_damaged);
     }
 
+    #[test]
+    fn longer_than_128_springs() {
+        let row: SpringRow = "????????????????????????????? 1".parse().unwrap();
+        let row = row.repeat(5);
+        assert!(row.pattern.len() > 128);
+        // 5 groups of 1 in 149 cells: choose 5 out of 149 - 5 + 1 slots
+        assert_eq!(solve_recursive(&row), 498187404);
+    }
+
+    /// Straightforward recursive count with no memoization, used as a
+    /// reference for [solve_recursive].
+    fn count_without_cache(pattern: &[Spring], groups: &[usize]) -> u64 {
+        let Some((&group, rest)) = groups.split_first() else {
+            return pattern.iter().all(|&s| s != Spring::Damaged) as u64;
+        };
+        let mut result = 0;
+        for start in 0..pattern.len() {
+            let end = start + group;
+            if end > pattern.len() {
+                break;
+            }
+            let fits = pattern[start..end]
+                .iter()
+                .all(|&s| s != Spring::Operational);
+            if fits {
+                if end == pattern.len() {
+                    result += count_without_cache(&[], rest);
+                } else if pattern[end] != Spring::Damaged {
+                    result += count_without_cache(&pattern[end + 1..], rest);
+                }
+            }
+            if pattern[start] == Spring::Damaged {
+                break;
+            }
+        }
+        result
+    }
+
+    /// Generate a row by picking a concrete arrangement and then hiding some
+    /// of its springs, so that the row is always satisfiable.
+    fn random_row(next_random: &mut impl FnMut() -> u64) -> SpringRow {
+        loop {
+            let length = 1 + (next_random() % 20) as usize;
+            let concrete: Vec<_> = (0..length)
+                .map(|_| {
+                    if next_random().is_multiple_of(2) {
+                        Spring::Damaged
+                    } else {
+                        Spring::Operational
+                    }
+                })
+                .collect();
+            let known_damaged: Vec<_> = concrete
+                .split(|&s| s == Spring::Operational)
+                .map(|group| group.len())
+                .filter(|&len| len > 0)
+                .collect();
+            if known_damaged.is_empty() {
+                continue;
+            }
+            let pattern = concrete
+                .into_iter()
+                .map(|s| {
+                    if next_random().is_multiple_of(3) {
+                        s
+                    } else {
+                        Spring::Unknown
+                    }
+                })
+                .collect();
+            return SpringRow {
+                pattern,
+                known_damaged,
+            };
+        }
+    }
+
+    #[test]
+    fn memoized_matches_reference() {
+        // xorshift64, good enough for generating test cases
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let mut next_random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2000 {
+            let row = random_row(&mut next_random);
+            assert_eq!(
+                solve_recursive(&row),
+                count_without_cache(&row.pattern, &row.known_damaged),
+                "{row}"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn reusable_solver_benchmark() {
+        use std::time::Instant;
+
+        let input = parse(include_str!("../input/2023/day12.txt"));
+        let unfolded: Vec<_> = input.iter().map(|row| row.clone().repeat(5)).collect();
+
+        let start = Instant::now();
+        let fresh: u64 = unfolded.iter().map(solve_recursive).sum();
+        let fresh_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut solver = Solver::default();
+        let reused: u64 = unfolded.iter().map(|row| solver.solve(row)).sum();
+        let reused_time = start.elapsed();
+
+        println!("fresh cache: {fresh_time:?}, reused cache: {reused_time:?}");
+        assert_eq!(fresh, 2128386729962);
+        assert_eq!(reused, fresh);
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn dense_cache_benchmark() {
+        use std::time::Instant;
+
+        let input = parse(include_str!("../input/2023/day12.txt"));
+        let unfolded: Vec<_> = input.iter().map(|row| row.clone().repeat(5)).collect();
+
+        let start = Instant::now();
+        let with_hashmap: u64 = unfolded
+            .iter()
+            .map(|row| {
+                let total_damaged = row.known_damaged.iter().sum();
+                let mut partial = vec![Spring::Operational; row.pattern.len()];
+                let mut cache = HashMap::new();
+                solve_partial(&mut partial, 0, 0, total_damaged, &mut cache, row)
+            })
+            .sum();
+        let hashmap_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut solver = Solver::default();
+        let with_dense: u64 = unfolded.iter().map(|row| solver.solve(row)).sum();
+        let dense_time = start.elapsed();
+
+        println!("HashMap cache: {hashmap_time:?}, dense cache: {dense_time:?}");
+        assert_eq!(with_hashmap, 2128386729962);
+        assert_eq!(with_dense, with_hashmap);
+    }
+
     #[test]
     fn specific_thingy_that_takes_a_long_time() {
         let row: SpringRow = "???.??##?????.????? 1,4,1,1,1,1".parse().unwrap();
         let result = solve_recursive(&row);
         assert_eq!(result, 101);
 
-        for i in 1..=5 {
-            let row = row.clone().repeat(i);
-            let result = solve_recursive(&row);
-            println!("{i}: {result}");
+        for (i, result) in arrangement_growth(&row, 5).into_iter().enumerate() {
+            println!("{}: {result}", i + 1);
         }
     }
+
+    #[test]
+    fn growth_with_factor() {
+        let row: SpringRow = ".# 1".parse().unwrap();
+        assert_eq!(arrangement_growth(&row, 5), vec![1, 1, 1, 1, 1]);
+        let row: SpringRow = "?###???????? 3,2,1".parse().unwrap();
+        assert_eq!(arrangement_growth(&row, 2), vec![10, 150]);
+        assert_eq!(arrangement_growth(&row, 5)[4], 506250);
+    }
 }
 
 example_tests! {
@@ -709,10 +1132,12 @@ example_tests! {
     ",
     part1 => 21,
     part2 => 525152,
+    part2_threaded => 525152,
 }
 
 known_input_tests! {
     input: include_str!("../input/2023/day12.txt"),
     part1 => 7251,
     part2 => 2128386729962,
+    part2_threaded => 2128386729962,
 }
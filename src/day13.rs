@@ -1,3 +1,4 @@
+use std::borrow::Borrow;
 use std::str::FromStr;
 
 use aoc_runner_derive::{aoc, aoc_generator};
@@ -40,7 +41,7 @@ fn pos(x: usize, y: usize) -> Pos {
     Pos { x, y }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Mirror {
     data: Vec<Tile>,
     width: usize,
@@ -50,9 +51,21 @@ struct Mirror {
 impl FromStr for Mirror {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let data = s.trim().chars().filter_map(|c| c.try_into().ok()).collect();
-        let width = s.lines().next().unwrap().len();
-        let height = s.lines().count();
+        let mut data = Vec::new();
+        let mut width = None;
+        let mut height = 0;
+        for line in s.trim().lines() {
+            // all lines must be the same length, or the flat indexing of data
+            // would be inconsistent
+            if *width.get_or_insert(line.len()) != line.len() {
+                return Err(());
+            }
+            for c in line.chars() {
+                data.push(c.try_into()?);
+            }
+            height += 1;
+        }
+        let width = width.ok_or(())?;
         Ok(Mirror {
             data,
             width,
@@ -82,22 +95,18 @@ impl Grid for &Mirror {
     }
 }
 
-/// Flip x-y coordinates of a mirror
-struct HorizontalMiror<'a>(&'a Mirror);
-
-impl Grid for HorizontalMiror<'_> {
-    #[track_caller]
-    fn get(&self, position: Pos) -> Tile {
-        // invert x and y
-        self.0.get(pos(position.y, position.x))
-    }
-
-    fn width(&self) -> usize {
-        self.0.height()
-    }
-
-    fn height(&self) -> usize {
-        self.0.width()
+impl Mirror {
+    /// Flip x-y coordinates of a mirror, so that horizontal reflections become
+    /// vertical ones.
+    fn transpose(&self) -> Mirror {
+        let data = (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| self.get(pos(x, y))))
+            .collect();
+        Mirror {
+            data,
+            width: self.height,
+            height: self.width,
+        }
     }
 }
 
@@ -118,31 +127,161 @@ fn find_reflection<G: Grid>(grid: G) -> Option<usize> {
 }
 
 fn find_reflection_with_tolerance<G: Grid>(grid: G, tolerance: u32) -> Option<usize> {
+    reflections_with_tolerance(grid, tolerance).next()
+}
+
+/// Iterate over all the vertical lines of reflection with exactly `tolerance`
+/// smudges.
+fn reflections_with_tolerance<G: Grid>(grid: G, tolerance: u32) -> impl Iterator<Item = usize> {
     // Squish columns into bitfields to make comparisons cheaper. But I never
     // proved that this is actually faster, but it works fine for counting with
     // tolerance, so I'm keeping it.
-    debug_assert!(grid.width() <= 64);
-    debug_assert!(grid.height() <= 64);
-    let mut columns = [0; 64];
-    (0..grid.width()).for_each(|x| {
-        columns[x] = (0..grid.height())
-            .map(|y| grid.get(pos(x, y)))
-            .fold(0, |acc, tile| (acc << 1) | (tile == Tile::Rock) as u64);
+    let columns: Option<Vec<u64>> = (grid.height() <= 64).then(|| {
+        (0..grid.width())
+            .map(|x| {
+                (0..grid.height())
+                    .map(|y| grid.get(pos(x, y)))
+                    .fold(0, |acc, tile| (acc << 1) | (tile == Tile::Rock) as u64)
+            })
+            .collect()
     });
-    for x in 1..grid.width() {
+    (1..grid.width()).filter(move |&x| {
         let width = (grid.width() - x).min(x);
         debug_assert!(width > 0);
-        let found: u32 = (0..width)
-            .map(|i| (columns[x - i - 1], columns[x + i]))
-            .map(|(a, b)| (a ^ b).count_ones())
-            .sum();
-        if found == tolerance {
-            return Some(x);
+        let found = if let Some(columns) = &columns {
+            count_smudges_in_bitfields(columns, x)
+        } else {
+            // columns don't fit in a u64, compare them tile by tile instead
+            count_smudges_by_tile(&grid, x, tolerance + 1)
+        };
+        found == tolerance
+    })
+}
+
+/// Count the bits that don't match across line `x`, where each line of the
+/// mirror (parallel to the reflection) is squished into a bitfield.
+fn count_smudges_in_bitfields(lines: &[u64], x: usize) -> u32 {
+    let width = (lines.len() - x).min(x);
+    debug_assert!(width > 0);
+    (0..width)
+        .map(|i| (lines[x - i - 1], lines[x + i]))
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum()
+}
+
+/// Columns and rows of a mirror squished into bitfields.
+struct MirrorBitfields {
+    columns: Vec<u64>,
+    rows: Vec<u64>,
+}
+
+impl MirrorBitfields {
+    /// Squish the mirror in a single pass, if both rows and columns fit in a
+    /// u64.
+    fn new(mirror: &Mirror) -> Option<Self> {
+        if mirror.width > 64 || mirror.height > 64 {
+            return None;
+        }
+        let mut columns = vec![0; mirror.width];
+        let mut rows = vec![0; mirror.height];
+        for (y, row) in rows.iter_mut().enumerate() {
+            for (x, column) in columns.iter_mut().enumerate() {
+                let bit = (mirror.get(pos(x, y)) == Tile::Rock) as u64;
+                *column = (*column << 1) | bit;
+                *row = (*row << 1) | bit;
+            }
+        }
+        Some(Self { columns, rows })
+    }
+
+    fn find_reflection(&self, tolerance: u32) -> Option<Reflection> {
+        let find = |lines: &[u64]| {
+            (1..lines.len()).find(|&x| count_smudges_in_bitfields(lines, x) == tolerance)
+        };
+        find(&self.columns)
+            .map(Reflection::Vertical)
+            .or_else(|| find(&self.rows).map(Reflection::Horizontal))
+    }
+}
+
+/// Count the tiles that don't match across the vertical line `x`, stopping
+/// early when reaching `limit`.
+fn count_smudges_by_tile<G: Grid>(grid: &G, x: usize, limit: u32) -> u32 {
+    let width = (grid.width() - x).min(x);
+    (0..grid.height())
+        .flat_map(|y| (0..width).map(move |i| (pos(x - i - 1, y), pos(x + i, y))))
+        .filter(|&(a, b)| grid.get(a) != grid.get(b))
+        .take(limit as usize)
+        .count() as u32
+}
+
+/// All the vertical lines of reflection of a mirror with exactly `tolerance`
+/// smudges.
+#[cfg_attr(not(test), allow(unused))]
+fn all_reflections(mirror: &Mirror, tolerance: u32) -> Vec<usize> {
+    reflections_with_tolerance(mirror, tolerance).collect()
+}
+
+/// A line of reflection, identified by the number of columns to its left (if
+/// vertical) or rows above it (if horizontal).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reflection {
+    Vertical(usize),
+    Horizontal(usize),
+}
+
+impl Reflection {
+    /// The number that the puzzle wants for this reflection.
+    fn summary(self) -> usize {
+        match self {
+            Reflection::Vertical(columns) => columns,
+            Reflection::Horizontal(rows) => 100 * rows,
         }
     }
-    None
 }
 
+/// Find a reflection in a mirror, trying vertical lines first.
+fn find_any_reflection(mirror: &Mirror) -> Option<Reflection> {
+    find_reflection(mirror)
+        .map(Reflection::Vertical)
+        .or_else(|| find_reflection(&mirror.transpose()).map(Reflection::Horizontal))
+}
+
+/// Find a reflection with exactly `tolerance` smudges, trying vertical lines
+/// first.
+fn find_any_reflection_with_tolerance(mirror: &Mirror, tolerance: u32) -> Option<Reflection> {
+    if let Some(bitfields) = MirrorBitfields::new(mirror) {
+        return bitfields.find_reflection(tolerance);
+    }
+    find_reflection_with_tolerance(mirror, tolerance)
+        .map(Reflection::Vertical)
+        .or_else(|| {
+            find_reflection_with_tolerance(&mirror.transpose(), tolerance)
+                .map(Reflection::Horizontal)
+        })
+}
+
+/// Find the smudge that, once fixed, creates the reflection with tolerance 1.
+///
+/// Both tiles of the mismatching pair would work as the smudge; this returns
+/// the one on the right of (or below) the line of reflection, which is the one
+/// the problem statement names for the second example.
+#[cfg_attr(not(test), allow(unused))]
+fn smudge_position(mirror: &Mirror) -> Option<Pos> {
+    let first_mismatch = |grid: &Mirror, x: usize| {
+        let width = (grid.width - x).min(x);
+        (0..grid.height)
+            .flat_map(|y| (0..width).map(move |i| (pos(x - i - 1, y), pos(x + i, y))))
+            .find(|&(a, b)| grid.get(a) != grid.get(b))
+            .map(|(_, b)| b)
+    };
+    match find_any_reflection_with_tolerance(mirror, 1)? {
+        Reflection::Vertical(x) => first_mismatch(mirror, x),
+        Reflection::Horizontal(y) => first_mismatch(&mirror.transpose(), y).map(|p| pos(p.y, p.x)),
+    }
+}
+
+#[cfg_attr(not(feature = "extra-debug-prints"), allow(dead_code))]
 struct DisplayGrid<G: Grid>(G);
 
 impl<G: Grid> std::fmt::Display for DisplayGrid<G> {
@@ -158,85 +297,131 @@ impl<G: Grid> std::fmt::Display for DisplayGrid<G> {
     }
 }
 
-fn parse_mirrors(input: &str) -> impl Iterator<Item = Mirror> + '_ {
-    input.split("\n\n").map(|s| s.parse().unwrap())
+/// Display a mirror with plain ASCII tiles and markers on both sides of the
+/// line of reflection, like in the problem statement.
+#[cfg_attr(not(test), allow(dead_code))]
+struct DisplayReflection<'a>(&'a Mirror, Reflection);
+
+impl std::fmt::Display for DisplayReflection<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DisplayReflection(mirror, reflection) = *self;
+        let symbol = |x, y| match mirror.get(pos(x, y)) {
+            Tile::Ash => '.',
+            Tile::Rock => '#',
+        };
+        match reflection {
+            Reflection::Vertical(column) => {
+                let marker = format!(
+                    "{:>column$}{:<width$}",
+                    ">",
+                    "<",
+                    width = mirror.width - column
+                );
+                writeln!(f, "{marker}")?;
+                for y in 0..mirror.height {
+                    let row: String = (0..mirror.width).map(|x| symbol(x, y)).collect();
+                    writeln!(f, "{row}")?;
+                }
+                writeln!(f, "{marker}")
+            }
+            Reflection::Horizontal(row) => {
+                for y in 0..mirror.height {
+                    let marker = match y + 1 {
+                        n if n == row => 'v',
+                        n if n == row + 1 => '^',
+                        _ => ' ',
+                    };
+                    let line: String = (0..mirror.width).map(|x| symbol(x, y)).collect();
+                    writeln!(f, "{marker}{line}{marker}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_mirrors(input: &str) -> impl Iterator<Item = Result<Mirror, ()>> + '_ {
+    input.split("\n\n").map(str::parse)
 }
 
 #[aoc_generator(day13)]
 fn parse(input: &str) -> Vec<Mirror> {
-    parse_mirrors(input).collect()
+    parse_mirrors(input)
+        .enumerate()
+        .map(|(i, m)| m.unwrap_or_else(|()| panic!("mirror {i} is not a valid pattern")))
+        .collect()
 }
 
 #[aoc(day13, part1)]
 fn part1(input: &[Mirror]) -> usize {
-    input
-        .iter()
+    score_mirrors_with(input.iter(), find_any_reflection).sum()
+}
+
+/// Compute the reflection summary of each mirror as they come, finding
+/// reflections with `find`.
+fn score_mirrors_with<M: Borrow<Mirror>>(
+    mirrors: impl Iterator<Item = M>,
+    find: impl Fn(&Mirror) -> Option<Reflection>,
+) -> impl Iterator<Item = usize> {
+    mirrors
         .enumerate()
         .inspect(|(_i, _m)| {
             #[cfg(feature = "extra-debug-prints")]
             println!(
                 "Mirror {}:\n{}\n------------------------------",
                 _i,
-                DisplayGrid(*_m)
+                DisplayGrid(_m.borrow())
             );
         })
-        .map(|(i, m)| {
-            if let Some(cols) = find_reflection(m) {
-                cols
-            } else {
-                100 * find_reflection(HorizontalMiror(m))
-                    .unwrap_or_else(|| panic!("mirror {i} should be either vertical or horizontal"))
-            }
+        .map(move |(i, m)| {
+            find(m.borrow())
+                .unwrap_or_else(|| panic!("mirror {i} should be either vertical or horizontal"))
+                .summary()
         })
-        .sum()
+}
+
+/// Compute the reflection summary of each mirror as they come, allowing for
+/// exactly `tolerance` smudges in each of them.
+fn score_mirrors<M: Borrow<Mirror>>(
+    mirrors: impl Iterator<Item = M>,
+    tolerance: u32,
+) -> impl Iterator<Item = usize> {
+    score_mirrors_with(mirrors, move |mirror| {
+        find_any_reflection_with_tolerance(mirror, tolerance)
+    })
+}
+
+/// The reflection summary of each mirror, allowing for exactly `tolerance`
+/// smudges in each of them.
+fn mirror_scores(input: &[Mirror], tolerance: u32) -> Vec<usize> {
+    score_mirrors(input.iter(), tolerance).collect()
+}
+
+/// Same as [summarize_with_tolerance], but parsing the mirrors one at a time
+/// instead of collecting them first. Stops at the first pattern that fails to
+/// parse.
+#[cfg_attr(not(test), allow(unused))]
+fn summarize_streaming(input: &str, tolerance: u32) -> Result<usize, ()> {
+    let mut result = Ok(());
+    let mirrors = parse_mirrors(input).map_while(|m| m.map_err(|e| result = Err(e)).ok());
+    let total = score_mirrors(mirrors, tolerance).sum();
+    result.map(|()| total)
+}
+
+/// Sum the reflection summaries of all mirrors, allowing for exactly
+/// `tolerance` smudges in each of them.
+fn summarize_with_tolerance(input: &[Mirror], tolerance: u32) -> usize {
+    mirror_scores(input, tolerance).into_iter().sum()
 }
 
 #[aoc(day13, part1, bit_columns)]
 fn part1_bit_columns(input: &[Mirror]) -> usize {
-    input
-        .iter()
-        .enumerate()
-        .inspect(|(_i, _m)| {
-            #[cfg(feature = "extra-debug-prints")]
-            println!(
-                "Mirror {}:\n{}\n------------------------------",
-                _i,
-                DisplayGrid(*_m)
-            );
-        })
-        .map(|(i, m)| {
-            if let Some(cols) = find_reflection_with_tolerance(m, 0) {
-                cols
-            } else {
-                100 * find_reflection_with_tolerance(HorizontalMiror(m), 0)
-                    .unwrap_or_else(|| panic!("mirror {i} should be either vertical or horizontal"))
-            }
-        })
-        .sum()
+    summarize_with_tolerance(input, 0)
 }
 
 #[aoc(day13, part2)]
 fn part2(input: &[Mirror]) -> usize {
-    input
-        .iter()
-        .enumerate()
-        .inspect(|(_i, _m)| {
-            #[cfg(feature = "extra-debug-prints")]
-            println!(
-                "Mirror {}:\n{}\n------------------------------",
-                _i,
-                DisplayGrid(*_m)
-            );
-        })
-        .map(|(i, m)| {
-            if let Some(cols) = find_reflection_with_tolerance(m, 1) {
-                cols
-            } else {
-                100 * find_reflection_with_tolerance(HorizontalMiror(m), 1)
-                    .unwrap_or_else(|| panic!("mirror {i} should be either vertical or horizontal"))
-            }
-        })
-        .sum()
+    summarize_with_tolerance(input, 1)
 }
 
 #[cfg(test)]
@@ -249,7 +434,8 @@ mod tests {
         let mut iter = parse_mirrors(&input);
         let mirror = iter
             .next()
-            .expect("there should be at least one mirror in input");
+            .expect("there should be at least one mirror in input")
+            .expect("the mirror should be valid");
         assert!(
             iter.next().is_none(),
             "there should be exactly one mirror in input"
@@ -271,13 +457,11 @@ mod tests {
             ",
         );
         assert_eq!(find_reflection(&mirror), Some(5));
-        assert_eq!(find_reflection(HorizontalMiror(&mirror)), None);
+        assert_eq!(find_reflection(&mirror.transpose()), None);
+        assert_eq!(find_any_reflection(&mirror), Some(Reflection::Vertical(5)));
 
         assert_eq!(find_reflection_with_tolerance(&mirror, 0), Some(5));
-        assert_eq!(
-            find_reflection_with_tolerance(HorizontalMiror(&mirror), 0),
-            None
-        );
+        assert_eq!(find_reflection_with_tolerance(&mirror.transpose(), 0), None);
     }
 
     #[test]
@@ -294,15 +478,74 @@ mod tests {
             ",
         );
         assert_eq!(find_reflection(&mirror), None);
-        assert_eq!(find_reflection(HorizontalMiror(&mirror)), Some(4));
+        assert_eq!(find_reflection(&mirror.transpose()), Some(4));
+        assert_eq!(
+            find_any_reflection(&mirror),
+            Some(Reflection::Horizontal(4))
+        );
 
         assert_eq!(find_reflection_with_tolerance(&mirror, 0), None);
         assert_eq!(
-            find_reflection_with_tolerance(HorizontalMiror(&mirror), 0),
+            find_reflection_with_tolerance(&mirror.transpose(), 0),
             Some(4)
         );
     }
 
+    #[test]
+    fn transpose() {
+        let mirror = parse_one_example(
+            "
+            #...##..#
+            #....#..#
+            ..##..###
+            #####.##.
+            #####.##.
+            ..##..###
+            #....#..#
+            ",
+        );
+        let transposed = mirror.transpose();
+        assert_eq!(transposed.width, mirror.height);
+        assert_eq!(transposed.height, mirror.width);
+        assert_eq!((&transposed).get(pos(6, 2)), (&mirror).get(pos(2, 6)));
+        assert_eq!(transposed.transpose(), mirror);
+        assert_eq!(find_reflection(&transposed), Some(4));
+        assert_eq!(
+            find_any_reflection(&mirror),
+            find_reflection(&transposed).map(Reflection::Horizontal)
+        );
+    }
+
+    #[test]
+    fn display_reflection() {
+        let mirror = parse_one_example(
+            "
+            #.##..##.
+            ..#.##.#.
+            ##......#
+            ##......#
+            ..#.##.#.
+            ..##..##.
+            #.#.##.#.
+            ",
+        );
+        let reflection = find_any_reflection(&mirror).unwrap();
+        let rendered = DisplayReflection(&mirror, reflection).to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "    ><   ");
+        assert_eq!(lines[1], "#.##..##.");
+        assert_eq!(lines[8], "    ><   ");
+
+        let mirror = mirror.transpose();
+        let reflection = find_any_reflection(&mirror).unwrap();
+        let rendered = DisplayReflection(&mirror, reflection).to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[4], "v.#..#.#v");
+        assert_eq!(lines[5], "^.#..#.#^");
+        assert_eq!(lines[6], " #....#. ");
+    }
+
     #[test]
     fn part1_case_1() {
         // first mirror in input file
@@ -324,13 +567,10 @@ mod tests {
             ",
         );
         assert_eq!(find_reflection(&mirror), Some(11));
-        assert_eq!(find_reflection(HorizontalMiror(&mirror)), None);
+        assert_eq!(find_reflection(&mirror.transpose()), None);
 
         assert_eq!(find_reflection_with_tolerance(&mirror, 0), Some(11));
-        assert_eq!(
-            find_reflection_with_tolerance(HorizontalMiror(&mirror), 0),
-            None
-        );
+        assert_eq!(find_reflection_with_tolerance(&mirror.transpose(), 0), None);
     }
 
     #[test]
@@ -356,13 +596,139 @@ mod tests {
             ",
         );
         assert_eq!(find_reflection(&mirror), Some(8));
-        assert_eq!(find_reflection(HorizontalMiror(&mirror)), None);
+        assert_eq!(find_reflection(&mirror.transpose()), None);
 
         assert_eq!(find_reflection_with_tolerance(&mirror, 0), Some(8));
-        assert_eq!(
-            find_reflection_with_tolerance(HorizontalMiror(&mirror), 0),
-            None
+        assert_eq!(find_reflection_with_tolerance(&mirror.transpose(), 0), None);
+    }
+
+    #[test]
+    fn larger_than_64() {
+        // 70 columns of noise, with rows arranged to reflect after the third
+        let row = |k: u64| -> String {
+            (0..70u64)
+                .map(|x| {
+                    if ((x + 1) * (k + 3) * 2654435761 % 1000003).is_multiple_of(2) {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        };
+        let wide = [0, 1, 2, 2, 1, 0, 3].map(row).join("\n");
+        let tall = (0..70)
+            .map(|x| wide.lines().map(|line| &line[x..x + 1]).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let wide = parse(&wide);
+        let tall = parse(&tall);
+        assert_eq!(wide[0].width, 70);
+        assert_eq!(tall[0].height, 70);
+
+        assert_eq!(part1(&wide), 300);
+        assert_eq!(part1_bit_columns(&wide), 300);
+        assert_eq!(part1(&tall), 3);
+        assert_eq!(part1_bit_columns(&tall), 3);
+    }
+
+    #[test]
+    fn summarize_tolerance_zero_is_part1() {
+        let input = parse(&unindent::unindent(
+            "
+            #.##..##.
+            ..#.##.#.
+            ##......#
+            ##......#
+            ..#.##.#.
+            ..##..##.
+            #.#.##.#.
+
+            #...##..#
+            #....#..#
+            ..##..###
+            #####.##.
+            #####.##.
+            ..##..###
+            #....#..#
+            ",
+        ));
+        assert_eq!(summarize_with_tolerance(&input, 0), part1(&input));
+        assert_eq!(summarize_with_tolerance(&input, 1), 400);
+        assert_eq!(mirror_scores(&input, 0), vec![5, 400]);
+        assert_eq!(mirror_scores(&input, 1), vec![300, 100]);
+    }
+
+    #[test]
+    fn multiple_reflections() {
+        let mirror = parse_one_example(
+            "
+            #..##..#
+            .##..##.
+            ",
+        );
+        assert_eq!(all_reflections(&mirror, 0), vec![2, 4, 6]);
+        assert_eq!(find_reflection_with_tolerance(&mirror, 0), Some(2));
+    }
+
+    #[test]
+    fn parse_invalid_mirror() {
+        let ragged = unindent::unindent(
+            "
+            #.##..##.
+            ..#.##.
+            ##......#
+            ",
         );
+        assert!(ragged.parse::<Mirror>().is_err());
+        assert!("#.#\n#x#".parse::<Mirror>().is_err());
+        assert!("".parse::<Mirror>().is_err());
+    }
+
+    #[test]
+    fn precomputed_bitfields() {
+        let input = parse(include_str!("../input/2023/day13.txt"));
+        for tolerance in 0..=1 {
+            for mirror in &input {
+                let bitfields = MirrorBitfields::new(mirror).unwrap();
+                let expected = find_reflection_with_tolerance(mirror, tolerance)
+                    .map(Reflection::Vertical)
+                    .or_else(|| {
+                        find_reflection_with_tolerance(&mirror.transpose(), tolerance)
+                            .map(Reflection::Horizontal)
+                    });
+                assert_eq!(bitfields.find_reflection(tolerance), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let input = include_str!("../input/2023/day13.txt");
+        let mirrors = parse(input);
+        assert_eq!(summarize_streaming(input, 0), Ok(part1(&mirrors)));
+        assert_eq!(summarize_streaming(input, 1), Ok(part2(&mirrors)));
+    }
+
+    #[test]
+    fn streaming_invalid_mirror() {
+        let input = unindent::unindent(
+            "
+            #.##..##.
+            ..#.##.#.
+            ##......#
+            ##......#
+            ..#.##.#.
+            ..##..##.
+            #.#.##.#.
+
+            #.##..##.
+            ..#.##.
+            ##......#
+            ",
+        );
+        assert_eq!(summarize_streaming(&input, 0), Err(()));
+        assert_eq!(summarize_streaming(&input, 1), Err(()));
     }
 
     #[test]
@@ -380,9 +746,30 @@ mod tests {
         );
         assert_eq!(find_reflection_with_tolerance(&mirror, 1), None);
         assert_eq!(
-            find_reflection_with_tolerance(HorizontalMiror(&mirror), 1),
+            find_reflection_with_tolerance(&mirror.transpose(), 1),
             Some(3)
         );
+        // the problem statement fixes the upper-left corner, which is paired
+        // with the first symbol of the sixth row, below the line
+        assert_eq!(smudge_position(&mirror), Some(pos(0, 5)));
+    }
+
+    #[test]
+    fn smudge_position_horizontal() {
+        let mirror = parse_one_example(
+            "
+            #...##..#
+            #....#..#
+            ..##..###
+            #####.##.
+            #####.##.
+            ..##..###
+            #....#..#
+            ",
+        );
+        // the problem statement says to fix the fifth symbol of the second
+        // row, which is just below the line
+        assert_eq!(smudge_position(&mirror), Some(pos(4, 1)));
     }
 }
 
@@ -405,6 +792,8 @@ example_tests! {
     #....#..#
     ",
     part1 => 405,
+    part1_bit_columns => 405,
+    part2 => 400,
 }
 
 known_input_tests! {
@@ -34,6 +34,7 @@ impl FromGridLike for Grid {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RollDirection {
     North,
     South,
@@ -41,6 +42,14 @@ enum RollDirection {
     West,
 }
 
+/// The order of the directions in a full spin cycle.
+const SPIN_CYCLE: [RollDirection; 4] = [
+    RollDirection::North,
+    RollDirection::West,
+    RollDirection::South,
+    RollDirection::East,
+];
+
 impl Grid {
     fn clone_without_balls(&self) -> Self {
         let mut tiles = Vec::with_capacity(self.tiles.len());
@@ -68,91 +77,91 @@ impl Grid {
     }
 
     fn roll(&self, mut new_grid: Grid, direction: RollDirection) -> Self {
-        // Very repetitive code but I can't be bothered to make it generic and
-        // there are little differences between positive directions (nort/west)
-        // and negative directions (south/east) because we need to account for
-        // (un)signedness.
-        match direction {
-            RollDirection::North => {
-                for x in 0..self.width {
-                    let mut first_empty = 0;
-                    for y in 0..self.height {
-                        match self.get(x, y) {
-                            Tile::Empty => {}
-                            Tile::Ball => {
-                                new_grid.set(x, first_empty, Tile::Ball);
-                                first_empty += 1;
-                            }
-                            Tile::Obstacle => {
-                                first_empty = y + 1;
-                            }
-                        }
-                    }
-                }
-            }
-            RollDirection::South => {
-                for x in 0..self.width {
-                    let mut last_obstacle = self.height;
-                    for y in (0..self.height).rev() {
-                        match self.get(x, y) {
-                            Tile::Empty => {}
-                            Tile::Ball => {
-                                new_grid.set(x, last_obstacle - 1, Tile::Ball);
-                                last_obstacle -= 1;
-                            }
-                            Tile::Obstacle => {
-                                last_obstacle = y;
-                            }
-                        }
-                    }
-                }
-            }
-            RollDirection::West => {
-                for y in 0..self.height {
-                    let mut first_empty = 0;
-                    for x in 0..self.width {
-                        match self.get(x, y) {
-                            Tile::Empty => {}
-                            Tile::Ball => {
-                                new_grid.set(first_empty, y, Tile::Ball);
-                                first_empty += 1;
-                            }
-                            Tile::Obstacle => {
-                                first_empty = x + 1;
-                            }
-                        }
+        self.roll_onto(&mut new_grid, direction);
+        new_grid
+    }
+
+    /// Roll the balls of this grid onto `new_grid`, which must have no balls.
+    fn roll_onto(&self, new_grid: &mut Grid, direction: RollDirection) {
+        // Every direction is handled the same way: the grid is split in lines
+        // parallel to the direction, and each line is walked starting from the
+        // edge that balls roll towards. `to_xy` maps (line, offset from the
+        // edge) to actual grid coordinates.
+        let (lines, length) = match direction {
+            RollDirection::North | RollDirection::South => (self.width, self.height),
+            RollDirection::West | RollDirection::East => (self.height, self.width),
+        };
+        let to_xy = |line: usize, offset: usize| match direction {
+            RollDirection::North => (line, offset),
+            RollDirection::South => (line, self.height - 1 - offset),
+            RollDirection::West => (offset, line),
+            RollDirection::East => (self.width - 1 - offset, line),
+        };
+        for line in 0..lines {
+            let mut first_empty = 0;
+            for offset in 0..length {
+                let (x, y) = to_xy(line, offset);
+                match self.get(x, y) {
+                    Tile::Empty => {}
+                    Tile::Ball => {
+                        let (x, y) = to_xy(line, first_empty);
+                        new_grid.set(x, y, Tile::Ball);
+                        first_empty += 1;
                     }
-                }
-            }
-            RollDirection::East => {
-                for y in 0..self.height {
-                    let mut last_obstacle = self.width;
-                    for x in (0..self.width).rev() {
-                        match self.get(x, y) {
-                            Tile::Empty => {}
-                            Tile::Ball => {
-                                new_grid.set(last_obstacle - 1, y, Tile::Ball);
-                                last_obstacle -= 1;
-                            }
-                            Tile::Obstacle => {
-                                last_obstacle = x;
-                            }
-                        }
+                    Tile::Obstacle => {
+                        first_empty = offset + 1;
                     }
                 }
             }
         }
-        new_grid
     }
 
+    #[cfg_attr(not(test), allow(unused))]
     fn roll_cycle(&self, template: &Grid) -> Self {
-        let mut rolled = self.roll(template.clone(), RollDirection::North);
-        rolled = rolled.roll(template.clone(), RollDirection::West);
-        rolled = rolled.roll(template.clone(), RollDirection::South);
-        rolled = rolled.roll(template.clone(), RollDirection::East);
+        self.roll_sequence(template, &SPIN_CYCLE)
+    }
+
+    /// Roll the grid in each of the given directions, in order.
+    fn roll_sequence(&self, template: &Grid, directions: &[RollDirection]) -> Self {
+        let mut rolled = self.clone();
+        rolled.roll_sequence_in_place(&mut template.clone(), template, directions);
         rolled
     }
 
+    /// Same as [Grid::roll_cycle], but without allocating.
+    fn roll_cycle_in_place(&mut self, buffer: &mut Grid, template: &Grid) {
+        self.roll_sequence_in_place(buffer, template, &SPIN_CYCLE);
+    }
+
+    /// Same as [Grid::roll_sequence], but without allocating: each roll goes
+    /// into `buffer`, which is then swapped with `self`. The contents of
+    /// `buffer` on return are unspecified.
+    fn roll_sequence_in_place(
+        &mut self,
+        buffer: &mut Grid,
+        template: &Grid,
+        directions: &[RollDirection],
+    ) {
+        for &direction in directions {
+            buffer.tiles.copy_from_slice(&template.tiles);
+            self.roll_onto(buffer, direction);
+            std::mem::swap(self, buffer);
+        }
+    }
+
+    /// A compact representation of the positions of the balls, one bit per
+    /// tile. Obstacles never move, so this is enough to tell apart the states
+    /// of a grid while rolling it.
+    fn ball_bitset(&self) -> Vec<u64> {
+        let mut bits = vec![0; self.tiles.len().div_ceil(64)];
+        for (i, &tile) in self.tiles.iter().enumerate() {
+            if tile == Tile::Ball {
+                bits[i / 64] |= 1 << (i % 64);
+            }
+        }
+        bits
+    }
+
     fn weight(&self) -> usize {
         let mut row_weight = self.height;
         let mut total_weight = 0;
@@ -164,13 +173,31 @@ impl Grid {
     }
 }
 
-struct DisplayGrid<'a>(&'a Grid);
+/// Number of balls in each row of the grid, from top to bottom.
+#[cfg_attr(not(test), allow(unused))]
+fn ball_counts_by_row(grid: &Grid) -> Vec<usize> {
+    grid.tiles
+        .chunks(grid.width)
+        .map(|row| row.iter().filter(|&&tile| tile == Tile::Ball).count())
+        .collect()
+}
+
+/// Display a grid, optionally marking with `o` the positions of the balls in
+/// a previous state of the grid that are now empty.
+#[cfg_attr(not(test), allow(dead_code))]
+struct DisplayGrid<'a>(&'a Grid, Option<&'a Grid>);
 
 impl core::fmt::Display for DisplayGrid<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for y in 0..self.0.height {
             for x in 0..self.0.width {
-                write!(f, "{}", self.0.get(x, y))?;
+                let tile = self.0.get(x, y);
+                let was_ball = self.1.is_some_and(|orig| orig.get(x, y) == Tile::Ball);
+                if tile == Tile::Empty && was_ball {
+                    write!(f, "o")?;
+                } else {
+                    write!(f, "{}", tile)?;
+                }
             }
             writeln!(f)?;
         }
@@ -183,34 +210,58 @@ fn parse(input: &[u8]) -> Grid {
     input.grid_like().unwrap().into_grid()
 }
 
+/// Weight of the grid after rolling it once in the given direction.
+fn weight_after_roll(grid: &Grid, direction: RollDirection) -> usize {
+    let template = grid.clone_without_balls();
+    grid.roll(template, direction).weight()
+}
+
 #[aoc(day14, part1)]
 fn part1(grid: &Grid) -> usize {
-    let template = grid.clone_without_balls();
-    let rolled = grid.roll(template, RollDirection::North);
-    rolled.weight()
+    weight_after_roll(grid, RollDirection::North)
 }
 
-#[aoc(day14, part2)]
-fn part2(grid: &Grid) -> usize {
-    let template = grid.clone_without_balls();
+/// Roll the grid until it gets back to a previous state, and return the
+/// number of roll cycles before the repeating part starts and the length of
+/// the repeating part.
+fn find_cycle(grid: &Grid, template: &Grid) -> (usize, usize) {
     let mut history = std::collections::HashMap::new();
     let mut rolled = grid.clone();
-    const TARGET_ROLL_CYCLES: usize = 1_000_000_000;
-    for i in 0..TARGET_ROLL_CYCLES {
-        if let Some(&prev_i) = history.get(&rolled) {
-            let remaining = (TARGET_ROLL_CYCLES - i).rem(i - prev_i);
-            for _ in 0..remaining {
-                rolled = rolled.roll_cycle(&template);
-            }
-            break;
-        } else {
-            history.insert(rolled.clone(), i);
+    let mut buffer = template.clone();
+    for i in 0.. {
+        let key = rolled.ball_bitset();
+        if let Some(&prev_i) = history.get(&key) {
+            return (prev_i, i - prev_i);
         }
-        rolled = rolled.roll_cycle(&template);
+        history.insert(key, i);
+        rolled.roll_cycle_in_place(&mut buffer, template);
+    }
+    unreachable!()
+}
+
+/// Weight of the grid after `n` full roll cycles.
+fn weight_after_cycles(grid: &Grid, n: usize) -> usize {
+    let template = grid.clone_without_balls();
+    let (cycle_start, cycle_length) = find_cycle(grid, &template);
+    let equivalent_cycles = if n < cycle_start {
+        n
+    } else {
+        cycle_start + (n - cycle_start).rem(cycle_length)
+    };
+    let mut rolled = grid.clone();
+    let mut buffer = template.clone();
+    for _ in 0..equivalent_cycles {
+        rolled.roll_cycle_in_place(&mut buffer, &template);
     }
     rolled.weight()
 }
 
+#[aoc(day14, part2)]
+fn part2(grid: &Grid) -> usize {
+    const TARGET_ROLL_CYCLES: usize = 1_000_000_000;
+    weight_after_cycles(grid, TARGET_ROLL_CYCLES)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,7 +271,7 @@ mod tests {
         parse(&input)
     }
 
-    const EXAMPLE: &'static [u8] = b"
+    const EXAMPLE: &[u8] = b"
         O....#....
         O.OO#....#
         .....##...
@@ -316,6 +367,215 @@ mod tests {
         let result = input.roll(input.clone_without_balls(), RollDirection::West);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn part1_weight_after_roll() {
+        let input = parse_unindented(EXAMPLE);
+        assert_eq!(weight_after_roll(&input, RollDirection::North), 136);
+        // rolling east or west doesn't move balls between rows
+        assert_eq!(
+            weight_after_roll(&input, RollDirection::East),
+            input.weight()
+        );
+        assert_eq!(
+            weight_after_roll(&input, RollDirection::West),
+            input.weight()
+        );
+    }
+
+    #[test]
+    fn ball_counts() {
+        let input = parse_unindented(EXAMPLE);
+        let counts = ball_counts_by_row(&input);
+        assert_eq!(counts, vec![1, 3, 0, 4, 2, 2, 3, 1, 0, 2]);
+
+        let north = input.roll(input.clone_without_balls(), RollDirection::North);
+        let north_counts = ball_counts_by_row(&north);
+        assert_eq!(north_counts, vec![5, 2, 4, 3, 0, 0, 3, 1, 0, 0]);
+        assert_eq!(
+            north_counts.iter().sum::<usize>(),
+            counts.iter().sum::<usize>()
+        );
+
+        let east = input.roll(input.clone_without_balls(), RollDirection::East);
+        assert_eq!(ball_counts_by_row(&east), counts);
+    }
+
+    #[test]
+    fn display_moved_balls() {
+        let input = parse_unindented(EXAMPLE);
+        let north = input.roll(input.clone_without_balls(), RollDirection::North);
+        let expected = unindent::unindent(
+            "
+            OOOO.#.O..
+            OOoo#....#
+            OO..O##..O
+            Oo.#oOO..o
+            .o.....o#.
+            o.#..o.#.#
+            ..O..#oO.O
+            ..O....o..
+            #....###..
+            #oo..#....
+            ",
+        );
+        assert_eq!(DisplayGrid(&north, Some(&input)).to_string(), expected);
+        assert!(!DisplayGrid(&north, None).to_string().contains('o'));
+    }
+
+    #[test]
+    fn part2_roll_cycles() {
+        let input = parse_unindented(EXAMPLE);
+        let template = input.clone_without_balls();
+        let after_1 = input.roll_cycle(&template);
+        let after_2 = after_1.roll_cycle(&template);
+        let after_3 = after_2.roll_cycle(&template);
+        assert_eq!(
+            after_1,
+            parse_unindented(
+                b"
+                .....#....
+                ....#...O#
+                ...OO##...
+                .OO#......
+                .....OOO#.
+                .O#...O#.#
+                ....O#....
+                ......OOOO
+                #...O###..
+                #..OO#....
+                "
+            )
+        );
+        assert_eq!(
+            after_2,
+            parse_unindented(
+                b"
+                .....#....
+                ....#...O#
+                .....##...
+                ..O#......
+                .....OOO#.
+                .O#...O#.#
+                ....O#...O
+                .......OOO
+                #..OO###..
+                #.OOO#...O
+                "
+            )
+        );
+        assert_eq!(
+            after_3,
+            parse_unindented(
+                b"
+                .....#....
+                ....#...O#
+                .....##...
+                ..O#......
+                .....OOO#.
+                .O#...O#.#
+                ....O#...O
+                .......OOO
+                #...O###.O
+                #.OOO#...O
+                "
+            )
+        );
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn roll_cycle_in_place_benchmark() {
+        use std::time::Instant;
+
+        const CYCLES: usize = 1000;
+        let input = parse(include_bytes!("../input/2023/day14.txt"));
+        let template = input.clone_without_balls();
+
+        let start = Instant::now();
+        let mut cloned = input.clone();
+        for _ in 0..CYCLES {
+            cloned = cloned.roll(template.clone(), RollDirection::North);
+            cloned = cloned.roll(template.clone(), RollDirection::West);
+            cloned = cloned.roll(template.clone(), RollDirection::South);
+            cloned = cloned.roll(template.clone(), RollDirection::East);
+        }
+        let cloned_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut in_place = input.clone();
+        let mut buffer = template.clone();
+        for _ in 0..CYCLES {
+            in_place.roll_cycle_in_place(&mut buffer, &template);
+        }
+        let in_place_time = start.elapsed();
+
+        println!("cloning: {cloned_time:?}, in place: {in_place_time:?}");
+        assert_eq!(in_place, cloned);
+    }
+
+    #[test]
+    fn part2_roll_sequence() {
+        use RollDirection::*;
+
+        let input = parse_unindented(EXAMPLE);
+        let template = input.clone_without_balls();
+        assert_eq!(
+            input.roll_sequence(&template, &[North, West, South, East]),
+            input.roll_cycle(&template)
+        );
+        assert_eq!(input.roll_sequence(&template, &[]), input);
+        assert_eq!(
+            input.roll_sequence(&template, &[North]),
+            input.roll(template.clone(), North)
+        );
+    }
+
+    #[test]
+    fn part2_weight_after_one_cycle() {
+        let input = parse_unindented(EXAMPLE);
+        let expected = parse_unindented(
+            b"
+            .....#....
+            ....#...O#
+            ...OO##...
+            .OO#......
+            .....OOO#.
+            .O#...O#.#
+            ....O#....
+            ......OOOO
+            #...O###..
+            #..OO#....
+            ",
+        );
+        assert_eq!(weight_after_cycles(&input, 1), expected.weight());
+        assert_eq!(weight_after_cycles(&input, 1), 87);
+        assert_eq!(weight_after_cycles(&input, 0), input.weight());
+    }
+
+    #[test]
+    fn ball_bitset() {
+        let input = parse_unindented(EXAMPLE);
+        let mut without_obstacles = input.clone();
+        for tile in without_obstacles.tiles.iter_mut() {
+            if *tile == Tile::Obstacle {
+                *tile = Tile::Empty;
+            }
+        }
+        assert_ne!(input, without_obstacles);
+        assert_eq!(input.ball_bitset(), without_obstacles.ball_bitset());
+        assert_eq!(input.ball_bitset().len(), 2);
+
+        let rolled = input.roll(input.clone_without_balls(), RollDirection::North);
+        assert_ne!(input.ball_bitset(), rolled.ball_bitset());
+    }
+
+    #[test]
+    fn part2_find_cycle() {
+        let input = parse_unindented(EXAMPLE);
+        let (cycle_start, cycle_length) = find_cycle(&input, &input.clone_without_balls());
+        assert_eq!((cycle_start, cycle_length), (3, 7));
+    }
 }
 
 example_tests! {
@@ -1,5 +1,5 @@
+use core::mem::MaybeUninit;
 use core::panic;
-use std::ffi::CStr;
 
 use aoc_runner_derive::aoc;
 
@@ -12,38 +12,44 @@ fn hash_d15<I: IntoIterator<Item = u8>>(iter: I) -> u8 {
         .fold(0u8, |acc, b| b.wrapping_add(acc).wrapping_mul(17))
 }
 
+/// Hash a string with the HASH algorithm.
+fn hash(s: &[u8]) -> u8 {
+    hash_d15(s.iter().copied())
+}
+
 trait HashableD15 {
     fn hashed_d15(&self) -> u8;
 }
 
 impl HashableD15 for &[u8] {
     fn hashed_d15(&self) -> u8 {
-        hash_d15(self.iter().copied())
+        hash(self)
     }
 }
 
 impl<const N: usize> HashableD15 for &[u8; N] {
     fn hashed_d15(&self) -> u8 {
-        hash_d15(self.iter().copied())
+        hash(*self)
     }
 }
 
+/// Maximum length of a label. The labels in the input are much shorter than
+/// this, but let's leave some room.
+const MAX_LABEL_LEN: usize = 16;
+
+/// A label, padded with zeroes.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
-struct Label([u8; 8]);
+struct Label([u8; MAX_LABEL_LEN]);
 
 impl core::fmt::Debug for Label {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let str = CStr::from_bytes_until_nul(&self.0)
-            .unwrap()
-            .to_str()
-            .unwrap();
-
-        f.pad(str)
+        let len = self.0.iter().position(|&b| b == 0).unwrap_or(self.0.len());
+        f.pad(&String::from_utf8_lossy(&self.0[..len]))
     }
 }
 
-impl From<[u8; 8]> for Label {
-    fn from(value: [u8; 8]) -> Self {
+impl From<[u8; MAX_LABEL_LEN]> for Label {
+    fn from(value: [u8; MAX_LABEL_LEN]) -> Self {
         Self(value)
     }
 }
@@ -67,14 +73,31 @@ struct Step {
     op: Op,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseError {
+    InvalidByte(u8),
+    LabelTooLong,
+    InvalidFocalLength,
+}
+
 impl Step {
     fn from_ascii(s: &[u8]) -> Step {
-        let mut label = [0; 8];
+        match Self::try_from_ascii(s) {
+            Ok(step) => step,
+            Err(err) => panic!("Invalid step {:?}: {:?}", String::from_utf8_lossy(s), err),
+        }
+    }
+
+    fn try_from_ascii(s: &[u8]) -> Result<Step, ParseError> {
+        let mut label = [0; MAX_LABEL_LEN];
         let mut hash: u8 = 0;
         let mut op = Op::Minus;
         for (i, &byte) in s.iter().enumerate() {
             match byte {
                 b'a'..=b'z' => {
+                    if i >= MAX_LABEL_LEN {
+                        return Err(ParseError::LabelTooLong);
+                    }
                     label[i] = byte;
                     hash = hash.wrapping_add(byte).wrapping_mul(17);
                 }
@@ -83,15 +106,22 @@ impl Step {
                     break;
                 }
                 b'=' => {
-                    op = Op::Eq(s[i + 1] - b'0');
+                    let digits = &s[i + 1..];
+                    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                        return Err(ParseError::InvalidFocalLength);
+                    }
+                    let focal_length = digits.iter().try_fold(0u8, |acc, &digit| {
+                        acc.checked_mul(10)?.checked_add(digit - b'0')
+                    });
+                    op = Op::Eq(focal_length.ok_or(ParseError::InvalidFocalLength)?);
                     break;
                 }
-                _ => panic!("Invalid byte in step: {}", byte),
+                _ => return Err(ParseError::InvalidByte(byte)),
             }
         }
         let label: Label = label.into();
         let box_ = label.hashed_d15() as _;
-        Step { label, box_, op }
+        Ok(Step { label, box_, op })
     }
 }
 
@@ -139,23 +169,20 @@ impl LensesBox {
 
 impl Default for Registry {
     fn default() -> Self {
-        let boxes = if cfg!(any(miri, nfeature = "no_dark_magic")) {
-            // A safe version for Miri, because we already know that the version below
-            // is unsound.
+        let boxes = if cfg!(not(feature = "day15-dark-magic")) {
             [(); 256].map(|_| LensesBox::default())
         } else {
-            // SAFETY: nope, this is just for fun. This is unsound af, for
-            // multiple reasons. It depends on the internal representation of
-            // Vec, and on the assumption that the pointer never gets
-            // dereferenced when capacity=0. Miri and rust-analyzer get very
-            // angry, and rightly so, because we are violating the non-null
-            // pointer invariant. And I don't even want to think about platforms
-            // where 0 is not the null pointer. But tests pass and this gets us
-            // a negligible, absolutely not worth it, speedup. Let me just have
-            // fun, ok?
+            // This used to be a `mem::zeroed()` that pretended all-zero bytes
+            // were a valid empty Vec, which is unsound. Filling the array in
+            // place is the closest sound thing, and it's still just for fun.
+            let mut boxes = [const { MaybeUninit::<LensesBox>::uninit() }; 256];
+            for box_ in &mut boxes {
+                box_.write(LensesBox::default());
+            }
+            // SAFETY: every element has been initialized above, and
+            // MaybeUninit<T> has the same layout as T.
             unsafe {
-                #[allow(invalid_value)]
-                core::mem::zeroed()
+                core::mem::transmute::<[MaybeUninit<LensesBox>; 256], [LensesBox; 256]>(boxes)
             }
         };
         Self { boxes }
@@ -175,8 +202,27 @@ impl Registry {
         };
     }
 
-    fn into_boxes(self) -> impl Iterator<Item = LensesBox> {
-        self.boxes.into_iter()
+    fn apply_steps(&mut self, steps: impl IntoIterator<Item = Step>) {
+        for step in steps {
+            self.apply_step(step);
+        }
+    }
+
+    /// The lenses in a box, in slot order.
+    #[cfg_attr(not(test), allow(unused))]
+    fn box_contents(&self, box_index: usize) -> &[Lens] {
+        &self.boxes[box_index].lenses
+    }
+
+    /// Iterate over all the lenses, with the index of their box and of their
+    /// slot in the box.
+    fn lenses(&self) -> impl Iterator<Item = (usize, usize, Lens)> + '_ {
+        self.boxes.iter().enumerate().flat_map(|(box_index, box_)| {
+            box_.lenses
+                .iter()
+                .enumerate()
+                .map(move |(slot_index, &lens)| (box_index, slot_index, lens))
+        })
     }
 }
 
@@ -192,25 +238,30 @@ fn part1(input: &[u8]) -> u64 {
     input
         .ascii_trim_end()
         .split(|&b| b == b',')
-        .map(|step| step.hashed_d15() as u64)
+        .map(|step| hash(step) as u64)
         .sum()
 }
 
+/// The focusing power of the lenses in each box.
+fn focusing_powers(reg: &Registry) -> Vec<u64> {
+    let mut powers = vec![0; reg.boxes.len()];
+    for (i, n, lens) in reg.lenses() {
+        powers[i] += lens.focal_length as u64 * (n as u64 + 1) * (i as u64 + 1);
+    }
+    powers
+}
+
+/// Apply all the steps in the input to an empty registry.
+fn build_registry(input: &[u8]) -> Registry {
+    let mut reg = Registry::default();
+    reg.apply_steps(parse_steps(input));
+    reg
+}
+
 #[aoc(day15, part2)]
 fn part2(input: &[u8]) -> u64 {
-    let mut reg = Registry::default();
-    for step in parse_steps(input) {
-        reg.apply_step(step);
-    }
-    reg.into_boxes()
-        .enumerate()
-        .map(|(i, box_)| {
-            box_.into_iter()
-                .enumerate()
-                .map(|(n, lens)| lens.focal_length as u64 * (n as u64 + 1) * (i as u64 + 1))
-                .sum::<u64>()
-        })
-        .sum()
+    let reg = build_registry(input);
+    focusing_powers(&reg).into_iter().sum()
 }
 
 #[cfg(test)]
@@ -232,6 +283,156 @@ mod tests {
         assert_eq!(b"ot=7".hashed_d15(), 231);
     }
 
+    // runs with and without the day15-dark-magic feature
+    #[test]
+    fn default_registry() {
+        let reg = Registry::default();
+        assert!(reg.boxes.iter().all(|box_| box_.lenses.is_empty()));
+        assert_eq!(
+            part2(b"rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7"),
+            145
+        );
+    }
+
+    #[test]
+    fn hash_string() {
+        assert_eq!(hash(b"HASH"), 52);
+        assert_eq!(hash(b""), 0);
+        assert_eq!(hash(b"rn=1"), b"rn=1".hashed_d15());
+    }
+
+    #[test]
+    fn long_label() {
+        let step = Step::from_ascii(b"abcdefghij=3");
+        assert_eq!(format!("{:?}", step.label), "abcdefghij");
+        assert_eq!(step.box_, hash(b"abcdefghij") as usize);
+        assert_eq!(step.op, Op::Eq(3));
+
+        let mut reg = Registry::default();
+        reg.apply_step(step);
+        reg.apply_step(Step::from_ascii(b"abcdefgh=5"));
+        assert_eq!(
+            reg.boxes[step.box_].lenses,
+            vec![Lens {
+                label: step.label,
+                focal_length: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn focusing_power_per_box() {
+        let mut reg = Registry::default();
+        for step in parse_steps(b"rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7") {
+            reg.apply_step(step);
+        }
+        let powers = focusing_powers(&reg);
+        assert_eq!(powers.len(), 256);
+        assert_eq!(powers[0], 5);
+        assert_eq!(powers[3], 140);
+        assert_eq!(powers.iter().sum::<u64>(), 145);
+    }
+
+    #[test]
+    fn all_lenses() {
+        let mut reg = Registry::default();
+        for step in parse_steps(b"rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7") {
+            reg.apply_step(step);
+        }
+        let lenses: Vec<_> = reg
+            .lenses()
+            .map(|(box_, slot, lens)| (box_, slot, format!("{:?}", lens.label), lens.focal_length))
+            .collect();
+        let expected = [
+            (0, 0, "rn", 1),
+            (0, 1, "cm", 2),
+            (3, 0, "ot", 7),
+            (3, 1, "ab", 5),
+            (3, 2, "pc", 6),
+        ]
+        .map(|(box_, slot, label, focal_length)| (box_, slot, label.to_string(), focal_length));
+        assert_eq!(lenses, expected);
+    }
+
+    #[test]
+    fn multi_digit_focal_length() {
+        let step = Step::from_ascii(b"ab=12");
+        assert_eq!(step.op, Op::Eq(12));
+        assert_eq!(step.box_, 3);
+        assert_eq!(Step::from_ascii(b"ab=99").op, Op::Eq(99));
+        assert_eq!(Step::from_ascii(b"ab=07").op, Op::Eq(7));
+    }
+
+    #[test]
+    fn debug_full_label() {
+        let step = Step::from_ascii(b"abcdefgh-");
+        assert_eq!(format!("{:?}", step.label), "abcdefgh");
+        let step = Step::from_ascii(b"abcdefghijklmnop-");
+        assert_eq!(format!("{:?}", step.label), "abcdefghijklmnop");
+    }
+
+    #[test]
+    fn invalid_steps() {
+        use ParseError::*;
+
+        assert_eq!(Step::try_from_ascii(b"rn=x"), Err(InvalidFocalLength));
+        assert_eq!(Step::try_from_ascii(b"rn="), Err(InvalidFocalLength));
+        assert_eq!(Step::try_from_ascii(b"rn=1x"), Err(InvalidFocalLength));
+        assert_eq!(Step::try_from_ascii(b"rn=256"), Err(InvalidFocalLength));
+        assert_eq!(Step::try_from_ascii(b"rN=1"), Err(InvalidByte(b'N')));
+        assert_eq!(
+            Step::try_from_ascii(b"abcdefghijklmnopq-"),
+            Err(LabelTooLong)
+        );
+        assert_eq!(Step::try_from_ascii(b"rn=1"), Ok(Step::from_ascii(b"rn=1")));
+    }
+
+    #[test]
+    fn apply_steps_one_at_a_time() {
+        let labels = |lenses: &[Lens]| -> Vec<_> {
+            lenses
+                .iter()
+                .map(|lens| (format!("{:?}", lens.label), lens.focal_length))
+                .collect()
+        };
+        let mut steps = parse_steps(b"rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7");
+        let mut reg = Registry::default();
+
+        reg.apply_steps(steps.by_ref().take(3));
+        assert_eq!(labels(reg.box_contents(0)), [("rn".to_string(), 1)]);
+        assert_eq!(labels(reg.box_contents(1)), [("qp".to_string(), 3)]);
+
+        reg.apply_steps(steps.by_ref().take(2));
+        assert_eq!(
+            labels(reg.box_contents(0)),
+            [("rn".to_string(), 1), ("cm".to_string(), 2)]
+        );
+        assert!(reg.box_contents(1).is_empty());
+
+        for step in steps {
+            reg.apply_steps([step]);
+        }
+        assert_eq!(
+            labels(reg.box_contents(3)),
+            [
+                ("ot".to_string(), 7),
+                ("ab".to_string(), 5),
+                ("pc".to_string(), 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn build_example_registry() {
+        let reg = build_registry(b"rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7\n");
+        let non_empty: Vec<_> = (0..256)
+            .filter(|&i| !reg.box_contents(i).is_empty())
+            .collect();
+        assert_eq!(non_empty, [0, 3]);
+        assert_eq!(reg.box_contents(0).len(), 2);
+        assert_eq!(reg.box_contents(3).len(), 3);
+    }
+
     #[test]
     fn hash_example_labels() {
         assert_eq!(b"rn".hashed_d15(), 0);
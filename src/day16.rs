@@ -1,4 +1,7 @@
-use std::{collections::HashSet, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    vec,
+};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
@@ -182,31 +185,69 @@ impl Grid {
                 debug_assert!(energized_grid.get(beam.pos) == EnergizedState::Energized);
                 continue;
             }
-            let Some((beam, cell)) = self.beam_ray(beam).find_map(|successor| {
-                // println!("            {successor:?}",);
-                energized_grid.set_energized(successor.pos);
-                let cell = self.get(successor.pos);
-                if cell.passes_through(successor.direction) {
-                    None
-                } else {
-                    Some((successor, cell))
-                }
-            }) else {
-                continue;
-            };
-            // println!("         -> {beam:?} {cell:?}");
-            if cell.is_mirror() {
-                beams.extend(self.beam_step(cell.mirror_turn_beam(beam)));
+            self.follow_ray(beam, energized_grid, &mut beams);
+        }
+    }
+
+    /// Follow a single beam in a straight line until it hits a mirror or a
+    /// splitter, energizing all the cells along the way. The beams coming out
+    /// of the mirror or splitter are pushed to `beams`.
+    fn follow_ray(&self, beam: Beam, energized_grid: &mut EnergizedGrid, beams: &mut Vec<Beam>) {
+        let Some((beam, cell)) = self.beam_ray(beam).find_map(|successor| {
+            // println!("            {successor:?}",);
+            energized_grid.set_energized(successor.pos);
+            let cell = self.get(successor.pos);
+            if cell.passes_through(successor.direction) {
+                None
             } else {
-                debug_assert!(cell.is_splitter());
-                if let Some((beam1, beam2)) = cell.splitter_split_beam(beam) {
-                    beams.extend(self.beam_step(beam1));
-                    beams.extend(self.beam_step(beam2));
-                } else {
-                    beams.extend(self.beam_step(beam));
+                Some((successor, cell))
+            }
+        }) else {
+            return;
+        };
+        // println!("         -> {beam:?} {cell:?}");
+        self.deflect(beam, cell, beams);
+    }
+
+    /// Push to `beams` the beams coming out of `cell` (a mirror or a splitter)
+    /// when `beam` hits it.
+    fn deflect(&self, beam: Beam, cell: Cell, beams: &mut Vec<Beam>) {
+        if cell.is_mirror() {
+            beams.extend(self.beam_step(cell.mirror_turn_beam(beam)));
+        } else {
+            debug_assert!(cell.is_splitter());
+            if let Some((beam1, beam2)) = cell.splitter_split_beam(beam) {
+                beams.extend(self.beam_step(beam1));
+                beams.extend(self.beam_step(beam2));
+            } else {
+                beams.extend(self.beam_step(beam));
+            }
+        }
+    }
+
+    /// List the beam states visited from `start`, one per cell crossed, in the
+    /// order they are followed. When a splitter sends beams both ways, one of
+    /// them is followed to the end before the other. States already visited
+    /// are not followed again, so loops only appear once.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn beam_trace(&self, start: Beam) -> Vec<Beam> {
+        let mut trace = Vec::new();
+        let mut visited = HashSet::new();
+        let mut beams = vec![start];
+        while let Some(beam) = beams.pop() {
+            for successor in self.beam_ray(beam) {
+                if !visited.insert(successor) {
+                    break;
+                }
+                trace.push(successor);
+                let cell = self.get(successor.pos);
+                if !cell.passes_through(successor.direction) {
+                    self.deflect(successor, cell, &mut beams);
+                    break;
                 }
             }
         }
+        trace
     }
 }
 
@@ -250,13 +291,26 @@ impl EnergizedGrid {
             self.energized_count += 1;
         }
     }
+
+    /// Energize all the cells that are energized in `other`.
+    fn merge(&mut self, other: &EnergizedGrid) {
+        debug_assert_eq!(self.cells.len(), other.cells.len());
+        for (cell, other) in self.cells.iter_mut().zip(&other.cells) {
+            if *cell == EnergizedState::NotEnergized && *other == EnergizedState::Energized {
+                *cell = EnergizedState::Energized;
+                self.energized_count += 1;
+            }
+        }
+    }
 }
 
+/// Display energized cells as `#`, and every other cell with its original
+/// glyph.
+#[cfg_attr(not(test), allow(dead_code))]
 struct DisplayGrid<'a>(&'a Grid, &'a EnergizedGrid);
 
 impl std::fmt::Display for DisplayGrid<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO: fancier display of both energized grid and original grid
         use EnergizedState::*;
         for y in 0..self.1.height {
             for x in 0..self.1.width {
@@ -266,11 +320,10 @@ impl std::fmt::Display for DisplayGrid<'_> {
                 };
                 let cell = self.0.get(pos);
                 let energized = self.1.get(pos);
-                let c = match (cell, energized) {
-                    (_, NotEnergized) => "⬛️",
-                    (_, Energized) => "⬜️",
-                };
-                write!(f, "{}", c)?;
+                match energized {
+                    NotEnergized => write!(f, "{}", cell)?,
+                    Energized => write!(f, "#")?,
+                }
             }
             writeln!(f)?;
         }
@@ -283,34 +336,121 @@ fn parse(input: &[u8]) -> Grid {
     input.grid_like().unwrap().into_grid()
 }
 
+/// Follow the beam entering the grid with `start` and return which cells end
+/// up energized.
+fn energized(input: &Grid, start: Beam) -> EnergizedGrid {
+    let mut energized_grid = EnergizedGrid::new(input);
+    input.follow_beams(vec![start], &mut energized_grid);
+    energized_grid
+}
+
 #[aoc(day16, part1)]
 fn part1(input: &Grid) -> usize {
-    let mut energized_grid = EnergizedGrid::new(input);
     let beam = Beam::new(Pos { x: 0, y: 0 }, BeamDirection::East);
-    input.follow_beams(vec![beam], &mut energized_grid);
+    let energized_grid = energized(input, beam);
     // println!("{}", DisplayGrid(input, &energized_grid));
     energized_grid.energized_count
 }
 
-#[aoc(day16, part2)]
-fn part2(input: &Grid) -> usize {
-    (0..input.width)
+/// All the beams entering the grid from one of its edges, pointing inwards.
+fn edge_starts(grid: &Grid) -> impl Iterator<Item = Beam> + '_ {
+    (0..grid.width)
         .map(|x| (x, 0, BeamDirection::South))
-        .chain((0..input.width).map(|x| (x, input.height - 1, BeamDirection::North)))
-        .chain((0..input.height).map(|y| (0, y, BeamDirection::East)))
-        .chain((0..input.height).map(|y| (input.width - 1, y, BeamDirection::West)))
+        .chain((0..grid.width).map(|x| (x, grid.height - 1, BeamDirection::North)))
+        .chain((0..grid.height).map(|y| (0, y, BeamDirection::East)))
+        .chain((0..grid.height).map(|y| (grid.width - 1, y, BeamDirection::West)))
         .map(|(x, y, direction)| {
-            let mut energized_grid = EnergizedGrid::new(input);
-            let beam = Beam::new(
+            Beam::new(
                 Pos {
                     x: x as u32,
                     y: y as u32,
                 },
                 direction,
-            );
-            input.follow_beams(vec![beam], &mut energized_grid);
-            energized_grid.energized_count
+            )
         })
+}
+
+/// Find the edge start that energizes the most cells, and how many it
+/// energizes.
+fn best_start(input: &Grid) -> (Beam, usize) {
+    edge_starts(input)
+        .map(|beam| (beam, energized(input, beam).energized_count))
+        .max_by_key(|&(_, count)| count)
+        .unwrap()
+}
+
+#[aoc(day16, part2)]
+fn part2(input: &Grid) -> usize {
+    best_start(input).1
+}
+
+/// Whether the beam entering the grid with `start` ends up going around in a
+/// loop.
+///
+/// [Grid::follow_beams] terminates anyway since it never follows the same beam
+/// twice; this is only a diagnostic. Two beams from a splitter meeting again
+/// downstream are not a loop.
+#[cfg_attr(not(test), allow(dead_code))]
+fn has_cycle(grid: &Grid, start: Beam) -> bool {
+    // depth-first search over beams, where the successors of a beam are the
+    // ones coming out of the first mirror or splitter it hits
+    let mut scratch = EnergizedGrid::new(grid);
+    let mut done = HashSet::new();
+    let mut path = HashSet::new();
+    let mut stack = vec![(start, false)];
+    while let Some((beam, expanded)) = stack.pop() {
+        if expanded {
+            path.remove(&beam);
+            done.insert(beam);
+            continue;
+        }
+        if done.contains(&beam) {
+            continue;
+        }
+        path.insert(beam);
+        stack.push((beam, true));
+        let mut successors = Vec::new();
+        grid.follow_ray(beam, &mut scratch, &mut successors);
+        for successor in successors {
+            if path.contains(&successor) {
+                return true;
+            }
+            if !done.contains(&successor) {
+                stack.push((successor, false));
+            }
+        }
+    }
+    false
+}
+
+/// Cells energized by each beam coming out of a mirror or splitter, so that
+/// starts that end up on the same beam don't need to follow it again.
+#[derive(Default)]
+struct BeamCache {
+    energized: HashMap<Beam, EnergizedGrid>,
+}
+
+impl BeamCache {
+    fn energized(&mut self, input: &Grid, start: Beam) -> EnergizedGrid {
+        let mut energized_grid = EnergizedGrid::new(input);
+        let mut beams = Vec::new();
+        input.follow_ray(start, &mut energized_grid, &mut beams);
+        for beam in beams {
+            let reached = self
+                .energized
+                .entry(beam)
+                .or_insert_with(|| energized(input, beam));
+            energized_grid.merge(reached);
+        }
+        energized_grid
+    }
+}
+
+#[aoc(day16, part2, cached)]
+fn part2_cached(input: &Grid) -> usize {
+    let mut cache = BeamCache::default();
+    edge_starts(input)
+        .map(|beam| cache.energized(input, beam).energized_count)
         .max()
         .unwrap()
 }
@@ -323,23 +463,40 @@ fn part2_threaded(input: &Grid) -> usize {
     use std::thread;
 
     thread::scope(|s| {
-        let threads: Vec<_> = (0..input.width)
-            .map(|x| (x, 0, BeamDirection::South))
-            .chain((0..input.width).map(|x| (x, input.height - 1, BeamDirection::North)))
-            .chain((0..input.height).map(|y| (0, y, BeamDirection::East)))
-            .chain((0..input.height).map(|y| (input.width - 1, y, BeamDirection::West)))
-            .map(|(x, y, direction)| {
-                let beam = Beam::new(
-                    Pos {
-                        x: x as u32,
-                        y: y as u32,
-                    },
-                    direction,
-                );
-                s.spawn(move || {
-                    let mut energized_grid = EnergizedGrid::new(input);
-                    input.follow_beams(vec![beam], &mut energized_grid);
-                    energized_grid.energized_count
+        let threads: Vec<_> = edge_starts(input)
+            .map(|beam| s.spawn(move || energized(input, beam).energized_count))
+            .collect();
+
+        threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .max()
+            .unwrap()
+    })
+}
+
+#[aoc(day16, part2, pool)]
+fn part2_pool(input: &Grid) -> usize {
+    // ok, so implementing a thread pool is not that bad: one worker per core,
+    // all pulling start beams from the same iterator
+    use std::sync::Mutex;
+    use std::thread;
+
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let queue = Mutex::new(edge_starts(input));
+
+    thread::scope(|s| {
+        let threads: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut best = 0;
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        let Some(beam) = next else {
+                            break best;
+                        };
+                        best = best.max(energized(input, beam).energized_count);
+                    }
                 })
             })
             .collect();
@@ -352,6 +509,180 @@ fn part2_threaded(input: &Grid) -> usize {
     })
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{GridError, InvalidCharacter};
+
+    const EXAMPLE: &[u8] = br"
+    .|...\....
+    |.-.\.....
+    .....|-...
+    ........|.
+    ..........
+    .........\
+    ..../.\\..
+    .-.-/..|..
+    .|....-|.\
+    ..//.|....
+    ";
+
+    fn example_grid() -> Grid {
+        parse(&unindent::unindent_bytes(EXAMPLE))
+    }
+
+    #[test]
+    fn energized_example() {
+        let grid = example_grid();
+        let start = Beam::new(Pos { x: 0, y: 0 }, BeamDirection::East);
+        let energized_grid = energized(&grid, start);
+        assert_eq!(energized_grid.energized_count, 46);
+        assert_eq!(
+            energized_grid.get(Pos { x: 0, y: 0 }),
+            EnergizedState::Energized
+        );
+        assert_eq!(
+            energized_grid.get(Pos { x: 9, y: 9 }),
+            EnergizedState::NotEnergized
+        );
+    }
+
+    #[test]
+    fn display_energized_example() {
+        let grid = example_grid();
+        let start = Beam::new(Pos { x: 0, y: 0 }, BeamDirection::East);
+        let energized_grid = energized(&grid, start);
+        let rendered = DisplayGrid(&grid, &energized_grid).to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "######....");
+        // the two mirrors at (2, 9) and (3, 9) are never hit
+        assert_eq!(lines[9], ".#//.#.#..");
+        assert_eq!(lines[9].as_bytes()[2], b'/');
+    }
+
+    #[test]
+    fn edge_starts_count() {
+        let grid = example_grid();
+        assert_eq!(edge_starts(&grid).count(), 2 * (grid.width + grid.height));
+
+        let grid = parse(b"..-\n.|.\n");
+        let starts: Vec<_> = edge_starts(&grid).collect();
+        assert_eq!(starts.len(), 2 * (3 + 2));
+        assert!(starts.iter().all(|beam| grid.contains(beam.pos)));
+        assert!(starts.contains(&Beam::new(Pos { x: 2, y: 1 }, BeamDirection::West)));
+        assert!(starts.contains(&Beam::new(Pos { x: 1, y: 1 }, BeamDirection::North)));
+    }
+
+    #[test]
+    fn cycle() {
+        let grid = parse(&unindent::unindent_bytes(
+            br"
+            ./.\
+            .|..
+            .\./
+            ",
+        ));
+        let start = Beam::new(Pos { x: 0, y: 1 }, BeamDirection::East);
+        assert!(has_cycle(&grid, start));
+        assert_eq!(energized(&grid, start).energized_count, 9);
+
+        let start = Beam::new(Pos { x: 0, y: 0 }, BeamDirection::South);
+        assert!(!has_cycle(&grid, start));
+    }
+
+    #[test]
+    fn no_cycle_when_split_beams_meet() {
+        let grid = parse(&unindent::unindent_bytes(
+            br"
+            /-...\
+            ......
+            \..|./
+            ......
+            ",
+        ));
+        // the splitter at (1, 0) sends beams both ways, and they both hit the
+        // splitter at (3, 2) from opposite sides
+        let start = Beam::new(Pos { x: 1, y: 3 }, BeamDirection::North);
+        assert!(!has_cycle(&grid, start));
+        assert_eq!(energized(&grid, start).energized_count, 18);
+    }
+
+    #[test]
+    fn trace_mirror_turn() {
+        let grid = parse(&unindent::unindent_bytes(
+            br"
+            .\.
+            ...
+            ",
+        ));
+        let start = Beam::new(Pos { x: 0, y: 0 }, BeamDirection::East);
+        assert_eq!(
+            grid.beam_trace(start),
+            [
+                start,
+                Beam::new(Pos { x: 1, y: 0 }, BeamDirection::East),
+                Beam::new(Pos { x: 1, y: 1 }, BeamDirection::South),
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_stops_at_cycle() {
+        let grid = parse(&unindent::unindent_bytes(
+            br"
+            ./.\
+            .|..
+            .\./
+            ",
+        ));
+        let start = Beam::new(Pos { x: 0, y: 1 }, BeamDirection::East);
+        let trace = grid.beam_trace(start);
+        let unique: HashSet<_> = trace.iter().copied().collect();
+        assert_eq!(unique.len(), trace.len());
+        let energized: HashSet<_> = trace.iter().map(|beam| beam.pos).collect();
+        assert_eq!(energized.len(), 9);
+    }
+
+    #[test]
+    fn ragged_grid() {
+        let grid = b".|.\n..\n-..\n".as_slice().grid_like::<Cell>();
+        assert!(matches!(
+            grid,
+            Err(GridError::RaggedLine {
+                line: 1,
+                len: 2,
+                expected: 3
+            })
+        ));
+        let grid = b".|.\n.x.\n".as_slice().grid_like::<Cell>();
+        assert!(matches!(
+            grid,
+            Err(GridError::InvalidCell(InvalidCharacter(b'x')))
+        ));
+    }
+
+    #[test]
+    fn best_start_example() {
+        let grid = example_grid();
+        let (beam, count) = best_start(&grid);
+        // as in the puzzle description
+        assert_eq!(count, 51);
+        assert_eq!(beam, Beam::new(Pos { x: 3, y: 0 }, BeamDirection::South));
+        assert_eq!(energized(&grid, beam).energized_count, count);
+    }
+
+    #[test]
+    fn cached_matches_uncached() {
+        let grid = example_grid();
+        let mut cache = BeamCache::default();
+        for start in edge_starts(&grid) {
+            assert_eq!(cache.energized(&grid, start), energized(&grid, start));
+        }
+        assert!(!cache.energized.is_empty());
+    }
+}
+
 example_tests! {
     br"
     .|...\....
@@ -367,10 +698,14 @@ example_tests! {
     ",
     part1 => 46,
     part2 => 51,
+    part2_cached => 51,
+    part2_pool => 51,
 }
 
 known_input_tests! {
     input: include_bytes!("../input/2023/day16.txt"),
     part1 => 8098,
     part2 => 8335,
+    part2_cached => 8335,
+    part2_pool => 8335,
 }
@@ -1,10 +1,13 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
 use crate::{
     testing::{example_tests, known_input_tests},
-    utils::{AsciiUtils, FromGridLike, InvalidCharacter},
+    utils::{AsciiUtils, FromGridLike, GridError, GridLike, InvalidCharacter},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,18 +31,47 @@ impl TryFrom<u8> for Cell {
     }
 }
 
+/// A [Cell] written as a hexadecimal digit, for costs up to 15
+struct HexCell(Cell);
+
+impl TryFrom<u8> for HexCell {
+    type Error = InvalidCharacter;
+    fn try_from(c: u8) -> Result<Self, InvalidCharacter> {
+        match c {
+            b'0'..=b'9' => Ok(Self(Cell { cost: c - b'0' })),
+            b'a'..=b'f' => Ok(Self(Cell {
+                cost: c - b'a' + 10,
+            })),
+            c => Err(InvalidCharacter(c)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Direction {
     Up,
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
-    fn all() -> impl Iterator<Item = Self> {
+    const ORTHOGONAL: &'static [Self] = {
         use Direction::*;
-        [Up, Down, Left, Right].iter().copied()
+        &[Up, Down, Left, Right]
+    };
+
+    const WITH_DIAGONALS: &'static [Self] = {
+        use Direction::*;
+        &[Up, Down, Left, Right, UpLeft, UpRight, DownLeft, DownRight]
+    };
+
+    fn index(self) -> usize {
+        self as usize
     }
 
     fn opposite(self) -> Self {
@@ -49,6 +81,10 @@ impl Direction {
             Down => Up,
             Left => Right,
             Right => Left,
+            UpLeft => DownRight,
+            UpRight => DownLeft,
+            DownLeft => UpRight,
+            DownRight => UpLeft,
         }
     }
 }
@@ -79,6 +115,11 @@ impl Pos {
         (self.x.abs_diff(other.x) + self.y.abs_diff(other.y)) as _
     }
 
+    /// Distance when moving diagonally is allowed
+    fn chebyshev_distance(&self, other: Self) -> u32 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
+
     fn step(self, dir: Direction) -> Option<Self> {
         use Direction::*;
         Some(match dir {
@@ -98,6 +139,10 @@ impl Pos {
                 x: self.x + 1,
                 y: self.y,
             },
+            UpLeft => self.step(Up)?.step(Left)?,
+            UpRight => self.step(Up)?.step(Right)?,
+            DownLeft => self.step(Down)?.step(Left)?,
+            DownRight => self.step(Down)?.step(Right)?,
         })
     }
 }
@@ -129,8 +174,12 @@ impl Grid {
         self.cells[pos.y() * self.width + pos.x()]
     }
 
-    fn neighbors(&self, pos: Pos) -> impl Iterator<Item = (Direction, Pos, Cell)> + '_ {
-        Direction::all().filter_map(move |dir| {
+    fn neighbors(
+        &self,
+        pos: Pos,
+        directions: &'static [Direction],
+    ) -> impl Iterator<Item = (Direction, Pos, Cell)> + '_ {
+        directions.iter().filter_map(move |&dir| {
             let new_pos = pos.step(dir)?;
             if self.contains(new_pos) {
                 let cell = self.get(new_pos);
@@ -142,6 +191,64 @@ impl Grid {
     }
 }
 
+/// How the crucible is allowed to move
+#[derive(Debug, Clone, Copy)]
+struct Rules {
+    min_steps_in_direction: u32,
+    max_steps_in_direction: u32,
+    allow_diagonals: bool,
+}
+
+impl Rules {
+    /// Panics if `max_steps_in_direction` is 0: the crucible could never move,
+    /// and there would be no room to record visited states.
+    fn new(min_steps_in_direction: u32, max_steps_in_direction: u32) -> Self {
+        assert!(
+            max_steps_in_direction >= 1,
+            "the crucible must be allowed at least one step in a direction"
+        );
+        Self {
+            min_steps_in_direction,
+            max_steps_in_direction,
+            allow_diagonals: false,
+        }
+    }
+
+    /// Also move diagonally, with the same limits on steps in one direction.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn with_diagonals(self) -> Self {
+        Self {
+            allow_diagonals: true,
+            ..self
+        }
+    }
+
+    /// Default A* heuristic for reaching `end`: the number of cells to go
+    /// through, which is a lower bound of the cost as long as no cell costs 0.
+    fn heuristic(&self, end: Pos) -> impl Fn(Pos) -> u32 {
+        let allow_diagonals = self.allow_diagonals;
+        move |pos: Pos| {
+            if allow_diagonals {
+                pos.chebyshev_distance(end)
+            } else {
+                pos.manhattan_distance(end)
+            }
+        }
+    }
+
+    fn directions(&self) -> &'static [Direction] {
+        if self.allow_diagonals {
+            Direction::WITH_DIAGONALS
+        } else {
+            Direction::ORTHOGONAL
+        }
+    }
+}
+
+/// Position, direction and steps in that direction: what identifies a node
+/// for the purpose of not visiting it twice.
+type State = (Pos, Direction, u32);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct SearchNode {
     pos: Pos,
@@ -149,6 +256,7 @@ struct SearchNode {
     heuristic: u32,
     direction: Direction,
     steps_in_direction: u32,
+    previous: Option<State>,
 }
 
 impl SearchNode {
@@ -159,15 +267,23 @@ impl SearchNode {
             heuristic,
             direction,
             steps_in_direction: 0,
+            previous: None,
         }
     }
+
+    fn state(&self) -> State {
+        (self.pos, self.direction, self.steps_in_direction)
+    }
 }
 
 impl Ord for SearchNode {
     fn cmp(&self, other: &Self) -> Ordering {
+        // among nodes with the same estimate, prefer the ones that went further,
+        // since their estimate is more likely to be accurate
         (self.cost + self.heuristic)
             .cmp(&(other.cost + other.heuristic))
             .reverse()
+            .then(self.cost.cmp(&other.cost))
     }
 }
 
@@ -177,6 +293,9 @@ impl PartialOrd for SearchNode {
     }
 }
 
+/// Cost of the cheapest path between any two cells of the grid, not counting
+/// the cost of `start`, or `None` if `end` can't be reached within the step
+/// limits.
 fn find_path(
     grid: &Grid,
     start: Pos,
@@ -184,39 +303,268 @@ fn find_path(
     min_steps_in_direction: u32,
     max_steps_in_direction: u32,
 ) -> Option<u32> {
+    let rules = Rules::new(min_steps_in_direction, max_steps_in_direction);
+    find_path_with_rules(grid, start, end, rules)
+}
+
+/// Like [find_path], with any [Rules] for how the crucible moves.
+fn find_path_with_rules(grid: &Grid, start: Pos, end: Pos, rules: Rules) -> Option<u32> {
+    let mut visited = DenseVisited::new(grid, rules);
+    let heuristic = rules.heuristic(end);
+    search(grid, start, end, rules, heuristic, &mut visited, |_| {}).map(|node| node.cost)
+}
+
+/// Like [find_path], but with a custom A* heuristic `h` estimating the cost
+/// from a position to `end`. With `|_| 0` this is Dijkstra's algorithm.
+///
+/// The result is only guaranteed to be the cheapest if `h` never
+/// overestimates.
+#[cfg_attr(not(test), allow(dead_code))]
+fn find_path_with_heuristic(
+    grid: &Grid,
+    start: Pos,
+    end: Pos,
+    min_steps_in_direction: u32,
+    max_steps_in_direction: u32,
+    h: impl Fn(Pos) -> u32,
+) -> Option<u32> {
+    let rules = Rules::new(min_steps_in_direction, max_steps_in_direction);
+    let mut visited = DenseVisited::new(grid, rules);
+    search(grid, start, end, rules, h, &mut visited, |_| {}).map(|node| node.cost)
+}
+
+/// Like [find_path_with_heuristic], but also return the number of nodes that
+/// were expanded by the search, to compare how much work different strategies
+/// take.
+#[cfg_attr(not(test), allow(dead_code))]
+fn find_path_counted(
+    grid: &Grid,
+    start: Pos,
+    end: Pos,
+    min_steps_in_direction: u32,
+    max_steps_in_direction: u32,
+    h: impl Fn(Pos) -> u32,
+) -> (Option<u32>, usize) {
+    let rules = Rules::new(min_steps_in_direction, max_steps_in_direction);
+    let mut visited = DenseVisited::new(grid, rules);
+    let mut expanded = 0;
+    let cost =
+        search(grid, start, end, rules, h, &mut visited, |_| expanded += 1).map(|node| node.cost);
+    (cost, expanded)
+}
+
+/// Like [find_path], but also return all the positions along the cheapest
+/// route, from `start` to `end` included.
+#[cfg_attr(not(test), allow(dead_code))]
+fn find_path_with_route(
+    grid: &Grid,
+    start: Pos,
+    end: Pos,
+    min_steps_in_direction: u32,
+    max_steps_in_direction: u32,
+) -> Option<(u32, Vec<Pos>)> {
+    let rules = Rules::new(min_steps_in_direction, max_steps_in_direction);
+    let mut came_from = HashMap::new();
+    let mut visited = DenseVisited::new(grid, rules);
+    let heuristic = rules.heuristic(end);
+    let node = search(grid, start, end, rules, heuristic, &mut visited, |node| {
+        came_from.insert(node.state(), node.previous);
+    })?;
+    let mut route = vec![node.pos];
+    let mut previous = node.previous;
+    while let Some(state) = previous {
+        route.push(state.0);
+        previous = came_from[&state];
+    }
+    route.push(start);
+    route.reverse();
+    Some((node.cost, route))
+}
+
+/// Minimal cost to reach each cell of the grid from the top-left corner, in
+/// row-major order. Cells that can't be reached have a cost of `u32::MAX`.
+#[cfg_attr(not(test), allow(dead_code))]
+fn cost_field(grid: &Grid, min_steps_in_direction: u32, max_steps_in_direction: u32) -> Vec<u32> {
+    let rules = Rules::new(min_steps_in_direction, max_steps_in_direction);
+    let start = Pos::new(0, 0);
+    // a target outside of the grid is never reached, so the search goes on
+    // until all the reachable states are expanded
+    let nowhere = Pos::new(grid.width, grid.height);
+    let mut field = vec![u32::MAX; grid.cells.len()];
+    field[0] = 0;
+    let mut visited = DenseVisited::new(grid, rules);
+    search(
+        grid,
+        start,
+        nowhere,
+        rules,
+        |_| 0,
+        &mut visited,
+        |node| {
+            if node.steps_in_direction >= min_steps_in_direction {
+                let cost = &mut field[node.pos.y() * grid.width + node.pos.x()];
+                *cost = (*cost).min(node.cost);
+            }
+        },
+    );
+    field
+}
+
+/// Like [find_path], but each step of the search moves in a straight line as
+/// far as allowed and then turns, so there is no need to track the number of
+/// steps in the same direction.
+#[cfg_attr(not(test), allow(dead_code))]
+fn find_path_jump(
+    grid: &Grid,
+    start: Pos,
+    end: Pos,
+    min_steps_in_direction: u32,
+    max_steps_in_direction: u32,
+) -> Option<u32> {
+    use std::cmp::Reverse;
     use std::collections::BinaryHeap;
+    use Direction::*;
 
+    // a state is a position and whether we got there moving horizontally, since
+    // the next move must be along the other axis
+    let index =
+        |pos: Pos, horizontal: bool| (pos.y() * grid.width + pos.x()) * 2 + horizontal as usize;
+    let mut best = vec![u32::MAX; grid.width * grid.height * 2];
     let mut queue = BinaryHeap::new();
-    let mut visited = std::collections::HashSet::new();
 
-    let eval_heuristic = |pos: Pos| pos.manhattan_distance(end);
+    // from the start we can go in any direction
+    for horizontal in [false, true] {
+        best[index(start, horizontal)] = 0;
+        queue.push(Reverse((0, start.y, start.x, horizontal)));
+    }
+
+    while let Some(Reverse((cost, y, x, horizontal))) = queue.pop() {
+        let pos = Pos { x, y };
+        if pos == end {
+            return Some(cost);
+        }
+        if cost > best[index(pos, horizontal)] {
+            continue;
+        }
+        let directions = if horizontal {
+            [Up, Down]
+        } else {
+            [Left, Right]
+        };
+        for direction in directions {
+            let mut next = pos;
+            let mut next_cost = cost;
+            for steps in 1..=max_steps_in_direction {
+                let Some(stepped) = next.step(direction).filter(|&p| grid.contains(p)) else {
+                    break;
+                };
+                next = stepped;
+                next_cost += grid.get(next).cost();
+                if steps < min_steps_in_direction {
+                    continue;
+                }
+                let next_index = index(next, !horizontal);
+                if next_cost < best[next_index] {
+                    best[next_index] = next_cost;
+                    queue.push(Reverse((next_cost, next.y, next.x, !horizontal)));
+                }
+            }
+        }
+    }
+    None
+}
+
+trait VisitedSet {
+    /// Mark `state` as visited, returning whether it was not visited before.
+    fn insert(&mut self, state: State) -> bool;
+}
+
+impl VisitedSet for HashSet<State> {
+    fn insert(&mut self, state: State) -> bool {
+        HashSet::insert(self, state)
+    }
+}
+
+/// A [VisitedSet] backed by a flat array with a slot for every possible state
+/// in a grid.
+struct DenseVisited {
+    visited: Vec<bool>,
+    width: usize,
+    directions: usize,
+    max_steps: usize,
+}
+
+impl DenseVisited {
+    fn new(grid: &Grid, rules: Rules) -> Self {
+        let directions = rules.directions().len();
+        let max_steps = rules.max_steps_in_direction as usize;
+        Self {
+            visited: vec![false; grid.width * grid.height * directions * max_steps],
+            width: grid.width,
+            directions,
+            max_steps,
+        }
+    }
+}
+
+impl VisitedSet for DenseVisited {
+    fn insert(&mut self, (pos, direction, steps): State) -> bool {
+        // steps in direction are always between 1 and max_steps
+        debug_assert!(steps >= 1 && steps as usize <= self.max_steps);
+        debug_assert!(direction.index() < self.directions);
+        let index = ((pos.y() * self.width + pos.x()) * self.directions + direction.index())
+            * self.max_steps
+            + (steps as usize - 1);
+        !std::mem::replace(&mut self.visited[index], true)
+    }
+}
+
+/// A* search from `start` to `end`, returning the node that reached `end`.
+///
+/// `visit` is called once for each node that is expanded.
+fn search(
+    grid: &Grid,
+    start: Pos,
+    end: Pos,
+    rules: Rules,
+    eval_heuristic: impl Fn(Pos) -> u32,
+    visited: &mut impl VisitedSet,
+    mut visit: impl FnMut(&SearchNode),
+) -> Option<SearchNode> {
+    use std::collections::BinaryHeap;
+
+    let mut queue = BinaryHeap::new();
+    let neighbors = |pos: Pos| grid.neighbors(pos, rules.directions());
 
     // initialize queue with neighbors of start position so that we always have
     // a valid direction in search nodes
-    for (dir, pos, cell) in grid.neighbors(start) {
+    for (dir, pos, cell) in neighbors(start) {
         let mut node = SearchNode::new(pos, cell.cost(), eval_heuristic(pos), dir);
         node.steps_in_direction = 1;
         queue.push(node);
     }
 
     while let Some(node) = queue.pop() {
-        if node.pos == end {
-            return Some(node.cost);
+        // the crucible can only stop at the end after enough steps in the
+        // same direction
+        if node.pos == end && node.steps_in_direction >= rules.min_steps_in_direction {
+            return Some(node);
         }
 
-        if !visited.insert((node.pos, node.direction, node.steps_in_direction)) {
+        if !visited.insert(node.state()) {
             continue;
         }
+        visit(&node);
 
-        for (direction, pos, cell) in grid.neighbors(node.pos) {
+        for (direction, pos, cell) in neighbors(node.pos) {
             let steps_in_direction = if node.direction == direction.opposite() {
                 continue;
             } else if node.direction == direction {
-                if node.steps_in_direction >= max_steps_in_direction {
+                if node.steps_in_direction >= rules.max_steps_in_direction {
                     continue;
                 }
                 node.steps_in_direction + 1
-            } else if node.steps_in_direction >= min_steps_in_direction {
+            } else if node.steps_in_direction >= rules.min_steps_in_direction {
                 // reset steps in direction when changing direction
                 1
             } else {
@@ -228,6 +576,7 @@ fn find_path(
                 steps_in_direction,
                 cost: node.cost + cell.cost(),
                 heuristic: eval_heuristic(pos),
+                previous: Some(node.state()),
             };
             queue.push(successor);
         }
@@ -235,49 +584,77 @@ fn find_path(
     None
 }
 
+/// Parse the grid, reporting the first byte that is not a digit, or lines of
+/// different lengths.
+fn try_parse(input: &[u8]) -> Result<Grid, GridError<InvalidCharacter>> {
+    Ok(input.grid_like()?.into_grid())
+}
+
+/// Like [try_parse], but with costs written as hexadecimal digits (`a` to `f`
+/// for 10 to 15).
+#[cfg_attr(not(test), allow(dead_code))]
+fn try_parse_hex(input: &[u8]) -> Result<Grid, GridError<InvalidCharacter>> {
+    let GridLike {
+        cells,
+        width,
+        height,
+    } = input.grid_like::<HexCell>()?;
+    let cells = cells.into_iter().map(|HexCell(cell)| cell).collect();
+    Ok(Grid::from_cells(cells, width, height))
+}
+
 #[aoc_generator(day17)]
 fn parse(input: &[u8]) -> Grid {
-    input.grid_like().unwrap().into_grid()
+    try_parse(input).unwrap()
 }
 
-#[aoc(day17, part1)]
-fn part1(grid: &Grid) -> u32 {
+/// Heat loss along the best path from the top-left to the bottom-right corner,
+/// for a crucible that moves at least `min_steps` and at most `max_steps` in
+/// the same direction.
+fn minimal_heat_loss(grid: &Grid, min_steps: u32, max_steps: u32) -> Option<u32> {
     let start = Pos::new(0, 0);
     let end = Pos::new(grid.width - 1, grid.height - 1);
+    find_path(grid, start, end, min_steps, max_steps)
+}
+
+#[aoc(day17, part1)]
+fn part1(grid: &Grid) -> u32 {
     const MIN_STEPS_IN_DIRECTION: u32 = 1;
     const MAX_STEPS_IN_DIRECTION: u32 = 3;
 
-    find_path(
-        grid,
-        start,
-        end,
-        MIN_STEPS_IN_DIRECTION,
-        MAX_STEPS_IN_DIRECTION,
-    )
-    .unwrap()
+    minimal_heat_loss(grid, MIN_STEPS_IN_DIRECTION, MAX_STEPS_IN_DIRECTION)
+        .expect("no path to the bottom-right corner within the step limits")
 }
 
 #[aoc(day17, part2)]
 fn part2(grid: &Grid) -> u32 {
-    let start = Pos::new(0, 0);
-    let end = Pos::new(grid.width - 1, grid.height - 1);
     const MIN_STEPS_IN_DIRECTION: u32 = 4;
     const MAX_STEPS_IN_DIRECTION: u32 = 10;
 
-    find_path(
-        grid,
-        start,
-        end,
-        MIN_STEPS_IN_DIRECTION,
-        MAX_STEPS_IN_DIRECTION,
-    )
-    .unwrap()
+    minimal_heat_loss(grid, MIN_STEPS_IN_DIRECTION, MAX_STEPS_IN_DIRECTION)
+        .expect("no path to the bottom-right corner within the step limits")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXAMPLE: &[u8] = b"
+        2413432311323
+        3215453535623
+        3255245654254
+        3446585845452
+        4546657867536
+        1438598798454
+        4457876987766
+        3637877979653
+        4654967986887
+        4564679986453
+        1224686865563
+        2546548887735
+        4322674655533
+        ";
+
     #[test]
     fn uniform_cost() {
         let input = b"1111\n".repeat(4);
@@ -287,14 +664,256 @@ mod tests {
 
     #[test]
     fn forced_turn() {
-        let input = b"911111\n119991".repeat(4);
-        let grid = parse(&input);
+        let grid = parse(b"911111\n119991\n911111\n119991\n911111");
         let start = Pos::new(0, 0);
         let end = Pos::new(grid.width - 1, grid.height - 1);
 
         let result = find_path(&grid, start, end, 1, 3);
         assert_eq!(result, Some(17));
     }
+
+    #[test]
+    fn other_step_limits() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        assert_eq!(minimal_heat_loss(&grid, 1, 3), Some(102));
+        assert_eq!(minimal_heat_loss(&grid, 4, 10), Some(94));
+        assert_eq!(minimal_heat_loss(&grid, 2, 5), Some(101));
+    }
+
+    #[test]
+    fn path_to_center() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        let start = Pos::new(0, 0);
+        let center = Pos::new(grid.width / 2, grid.height / 2);
+        assert_eq!(find_path(&grid, start, center, 1, 3), Some(54));
+        // and back, which is not necessarily the same since the cost of the
+        // start cell is not counted
+        assert_eq!(
+            find_path(&grid, center, start, 1, 3),
+            Some(54 + grid.get(start).cost() - grid.get(center).cost())
+        );
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn dense_visited_benchmark() {
+        use std::time::Instant;
+
+        let grid = parse(include_bytes!("../input/2023/day17.txt"));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(grid.width - 1, grid.height - 1);
+
+        for (min_steps, max_steps, expected) in [(1, 3, 668), (4, 10, 788)] {
+            let rules = Rules::new(min_steps, max_steps);
+            let time = Instant::now();
+            let mut visited = HashSet::new();
+            let with_hashset = search(
+                &grid,
+                start,
+                end,
+                rules,
+                rules.heuristic(end),
+                &mut visited,
+                |_| {},
+            )
+            .map(|node| node.cost);
+            let hashset_time = time.elapsed();
+
+            let time = Instant::now();
+            let mut visited = DenseVisited::new(&grid, rules);
+            let with_dense = search(
+                &grid,
+                start,
+                end,
+                rules,
+                rules.heuristic(end),
+                &mut visited,
+                |_| {},
+            )
+            .map(|node| node.cost);
+            let dense_time = time.elapsed();
+
+            println!("({min_steps}, {max_steps}) HashSet: {hashset_time:?}, dense: {dense_time:?}");
+            assert_eq!(with_hashset, Some(expected));
+            assert_eq!(with_dense, with_hashset);
+        }
+    }
+
+    #[test]
+    fn jump_matches_step_by_step() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(grid.width - 1, grid.height - 1);
+        for (min_steps, max_steps) in [(1, 3), (4, 10)] {
+            assert_eq!(
+                find_path_jump(&grid, start, end, min_steps, max_steps),
+                find_path(&grid, start, end, min_steps, max_steps),
+            );
+        }
+        assert_eq!(find_path_jump(&grid, start, end, 4, 10), Some(94));
+    }
+
+    #[test]
+    fn diagonals() {
+        let grid = parse(&b"11111\n".repeat(5));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(4, 4);
+        let rules = Rules::new(1, 3);
+        let orthogonal = find_path_with_rules(&grid, start, end, rules).unwrap();
+        let diagonal = find_path_with_rules(&grid, start, end, rules.with_diagonals()).unwrap();
+        assert_eq!(orthogonal, 8);
+        assert!(diagonal < orthogonal);
+        assert_eq!(diagonal, 5);
+    }
+
+    #[test]
+    fn no_path_within_step_limits() {
+        let grid = parse(b"11111");
+        let start = Pos::new(0, 0);
+        // three steps are not enough to stop, and there's no room to turn
+        let end = Pos::new(3, 0);
+        assert_eq!(find_path(&grid, start, end, 4, 10), None);
+        assert_eq!(find_path_jump(&grid, start, end, 4, 10), None);
+        // but four steps are
+        let end = Pos::new(4, 0);
+        assert_eq!(find_path(&grid, start, end, 4, 10), Some(4));
+        assert_eq!(minimal_heat_loss(&parse(b"1111"), 4, 10), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "within the step limits")]
+    fn part2_without_path() {
+        part2(&parse(b"1111"));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one step")]
+    fn no_steps_allowed() {
+        minimal_heat_loss(&parse(&unindent::unindent_bytes(EXAMPLE)), 0, 0);
+    }
+
+    #[test]
+    fn heuristics() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(grid.width - 1, grid.height - 1);
+        for (min_steps, max_steps, expected) in [(1, 3, 102), (4, 10, 94)] {
+            let manhattan = |pos: Pos| pos.manhattan_distance(end);
+            assert_eq!(
+                find_path_with_heuristic(&grid, start, end, min_steps, max_steps, manhattan),
+                Some(expected)
+            );
+            assert_eq!(
+                find_path_with_heuristic(&grid, start, end, min_steps, max_steps, |_| 0),
+                Some(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_character() {
+        let result = try_parse(b"123\n4X6\n789\n");
+        assert!(matches!(
+            result,
+            Err(GridError::InvalidCell(InvalidCharacter(b'X')))
+        ));
+        assert!(try_parse(b"123\n456\n789\n").is_ok());
+    }
+
+    #[test]
+    fn example_cost_field() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        let field = cost_field(&grid, 1, 3);
+        assert_eq!(field.len(), grid.width * grid.height);
+        assert_eq!(field[0], 0);
+        assert_eq!(field[1], 4);
+        assert_eq!(field.last(), Some(&part1(&grid)));
+
+        let field = cost_field(&grid, 4, 10);
+        assert_eq!(field.last(), Some(&part2(&grid)));
+        // can't stop right after the first step, so the cell next to the start
+        // must be reached the long way around
+        assert_eq!(field[1], 70);
+    }
+
+    #[test]
+    fn hex_costs() {
+        let grid = try_parse_hex(&b"ffff\n".repeat(4)).unwrap();
+        assert_eq!(part1(&grid), 6 * 15);
+
+        let grid = try_parse_hex(b"1a11\n1b11\n1cde\n1f91\n").unwrap();
+        assert_eq!(grid.get(Pos::new(1, 0)).cost(), 10);
+        assert_eq!(grid.get(Pos::new(1, 3)).cost(), 15);
+        assert_eq!(part1(&grid), 28);
+
+        // plain digits work just the same
+        let grid = try_parse_hex(&unindent::unindent_bytes(EXAMPLE)).unwrap();
+        assert_eq!(part1(&grid), 102);
+        assert!(matches!(
+            try_parse_hex(b"1g\n11\n"),
+            Err(GridError::InvalidCell(InvalidCharacter(b'g')))
+        ));
+    }
+
+    #[test]
+    fn expanded_nodes() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(grid.width - 1, grid.height - 1);
+
+        let manhattan = |pos: Pos| pos.manhattan_distance(end);
+        let (cost, a_star) = find_path_counted(&grid, start, end, 1, 3, manhattan);
+        assert_eq!(cost, find_path(&grid, start, end, 1, 3));
+        assert!(a_star > 0);
+
+        let (cost, dijkstra) = find_path_counted(&grid, start, end, 1, 3, |_| 0);
+        assert_eq!(cost, Some(102));
+        assert!(dijkstra >= a_star);
+    }
+
+    #[test]
+    fn tie_break_by_cost() {
+        use std::collections::BinaryHeap;
+
+        let pos = Pos::new(0, 0);
+        let mut queue = BinaryHeap::new();
+        queue.push(SearchNode::new(pos, 5, 10, Direction::Right));
+        queue.push(SearchNode::new(pos, 10, 5, Direction::Right));
+        queue.push(SearchNode::new(pos, 3, 11, Direction::Right));
+        queue.push(SearchNode::new(pos, 1, 5, Direction::Right));
+        let costs: Vec<_> = std::iter::from_fn(|| queue.pop().map(|node| node.cost)).collect();
+        assert_eq!(costs, [1, 3, 10, 5]);
+    }
+
+    #[test]
+    fn tie_break_keeps_real_input_answers() {
+        let grid = parse(include_bytes!("../input/2023/day17.txt"));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(grid.width - 1, grid.height - 1);
+        let manhattan = |pos: Pos| pos.manhattan_distance(end);
+        let (cost, expanded) = find_path_counted(&grid, start, end, 1, 3, manhattan);
+        assert_eq!(cost, Some(668));
+        assert!(expanded > 0);
+        let (cost, _) = find_path_counted(&grid, start, end, 4, 10, manhattan);
+        assert_eq!(cost, Some(788));
+    }
+
+    #[test]
+    fn route() {
+        let grid = parse(&unindent::unindent_bytes(EXAMPLE));
+        let start = Pos::new(0, 0);
+        let end = Pos::new(grid.width - 1, grid.height - 1);
+
+        let (cost, route) = find_path_with_route(&grid, start, end, 1, 3).unwrap();
+        assert_eq!(cost, 102);
+        assert_eq!(route.first(), Some(&start));
+        assert_eq!(route.last(), Some(&end));
+        for (a, b) in route.iter().zip(&route[1..]) {
+            assert_eq!(a.manhattan_distance(*b), 1);
+        }
+        let route_cost: u32 = route[1..].iter().map(|&pos| grid.get(pos).cost()).sum();
+        assert_eq!(route_cost, cost);
+    }
 }
 
 example_tests! {
@@ -116,28 +116,35 @@ impl Step {
 
     /// Parsing as defined in part 2. Color is always 0, 0, 0.
     fn parse_alternate(s: &str) -> Self {
-        let s = s
+        Self::try_parse_alternate(s).unwrap()
+    }
+
+    /// Like [Step::parse_alternate], but returns an error describing what's
+    /// wrong with the line instead of panicking.
+    fn try_parse_alternate(s: &str) -> Result<Self, String> {
+        let code = s
             .split_whitespace()
             .last()
-            .unwrap()
+            .ok_or_else(|| format!("empty line: {s:?}"))?
             .trim_matches(|c| c == '(' || c == ')' || c == '#');
-        debug_assert!(s.len() == 6);
-        let distance = u32::from_str_radix(&s[0..5], 16).unwrap();
-        let direction_code = s.chars().last().unwrap();
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("expected 6 hex digits, found {code:?}"));
+        }
+        let distance = u32::from_str_radix(&code[0..5], 16).unwrap();
         // 0 means R, 1 means D, 2 means L, and 3 means U.
-        let direction = match direction_code {
-            '0' => Direction::Right,
-            '1' => Direction::Down,
-            '2' => Direction::Left,
-            '3' => Direction::Up,
-            _ => panic!("invalid direction code"),
+        let direction = match &code[5..] {
+            "0" => Direction::Right,
+            "1" => Direction::Down,
+            "2" => Direction::Left,
+            "3" => Direction::Up,
+            c => return Err(format!("invalid direction code {c:?}")),
         };
         let color = Color::default();
-        Self {
+        Ok(Self {
             direction,
             distance: distance.try_into().unwrap(),
             color,
-        }
+        })
     }
 }
 
@@ -175,13 +182,29 @@ impl SegmentLoop {
             Some(*pos)
         }))
     }
+
+    /// Whether following all the steps leads back to the start.
+    fn is_closed(&self) -> bool {
+        let start = Position::default();
+        self.positions(start).last() == Some(start)
+    }
 }
 
 #[cfg(feature = "draw-visuals")]
 fn draw_loop_as_svg_path(segments: &SegmentLoop, file_name: &str) {
+    let svg = to_svg(segments);
+
+    use std::path::*;
+    let file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(file_name);
+    std::fs::write(file_path, svg).unwrap();
+}
+
+/// Render the loop as an SVG image, with the filled area in white and each
+/// segment drawn in its own color.
+#[cfg_attr(not(any(test, feature = "draw-visuals")), allow(dead_code))]
+fn to_svg(segments: &SegmentLoop) -> String {
     let start = Position::default();
     let positions: Vec<_> = segments.positions(start).collect();
-    dbg!(positions.len(), segments.steps.len());
     let mut path = "M 0,0".to_string();
     for pos in &positions[1..] {
         path.push_str(&format!(" L {},{}", pos.x, pos.y));
@@ -220,10 +243,7 @@ fn draw_loop_as_svg_path(segments: &SegmentLoop, file_name: &str) {
     }
 
     svg.push_str("</svg>");
-
-    use std::path::*;
-    let file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(file_name);
-    std::fs::write(file_path, svg).unwrap();
+    svg
 }
 
 fn parse_regular(input: &str) -> SegmentLoop {
@@ -234,25 +254,91 @@ fn parse_alternate(input: &str) -> SegmentLoop {
     SegmentLoop::new(input.lines().map(Step::parse_alternate).collect())
 }
 
+/// Parse the input both as in part 1 and as in part 2, in a single pass.
+#[cfg_attr(not(test), allow(dead_code))]
+fn parse_both(input: &str) -> (SegmentLoop, SegmentLoop) {
+    let (regular, alternate) = input
+        .lines()
+        .map(|line| (Step::parse_regular(line), Step::parse_alternate(line)))
+        .unzip();
+    (SegmentLoop::new(regular), SegmentLoop::new(alternate))
+}
+
 /// Compute area of a polygon given its vertices.
 fn shoelace_formula(vertices: &[Position]) -> isize {
+    // The sum is twice the area, and the single products can be even larger
+    // than that, so they can overflow even if the area fits in an isize.
+    // Accumulating as i128 takes care of that on any target.
+    fn cross(p1: Position, p2: Position) -> i128 {
+        -(p1.y as i128) * (p2.x as i128) + (p1.x as i128) * (p2.y as i128)
+    }
     let mut area = 0;
     for pp in vertices.windows(2) {
-        let p1 = pp[0];
-        let p2 = pp[1];
-        area += -p1.y * p2.x + p1.x * p2.y;
+        area += cross(pp[0], pp[1]);
     }
     let last = vertices.last().unwrap();
-    area += -last.y * vertices[0].x + last.x * vertices[0].y;
-    area.abs() / 2
+    area += cross(*last, vertices[0]);
+    (area.abs() / 2)
+        .try_into()
+        .expect("area should fit in an isize")
 }
 
-fn exterior_area_of_loop(segment_loop: &SegmentLoop) -> isize {
-    // We figure out which side is inside or outside using the same method as in
-    // day 10, by counting the turns
+/// Number of cells dug out by a loop going through the centers of the cells at
+/// `vertices`, in order, with only horizontal and vertical segments: the same
+/// as [interior_and_perimeter] but starting from a list of vertices. This is
+/// larger than the area of the polygon itself ([shoelace_formula]), because
+/// the cells on the border are counted whole.
+#[cfg_attr(not(test), allow(dead_code))]
+fn dug_cells(vertices: &[Position]) -> isize {
+    let Some(&last) = vertices.last() else {
+        return 0;
+    };
+    let border: isize = std::iter::once(last)
+        .chain(vertices.iter().copied())
+        .zip(vertices)
+        .map(|(p1, p2)| {
+            debug_assert!(p1.x == p2.x || p1.y == p2.y);
+            (p1.x - p2.x).abs() + (p1.y - p2.y).abs()
+        })
+        .sum();
+    // see interior_and_perimeter for why this works
+    shoelace_formula(vertices) + border / 2 + 1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LoopError {
+    /// The step at `index` doesn't turn 90 degrees from the previous one
+    InvalidTurn {
+        index: usize,
+        from: Direction,
+        to: Direction,
+    },
+    /// The steps don't lead back to the start
+    NotClosed,
+}
+
+impl std::fmt::Display for LoopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoopError::InvalidTurn { index, from, to } => write!(
+                f,
+                "only 90 degree turns expected, but step {index} goes {to:?} after {from:?}"
+            ),
+            LoopError::NotClosed => write!(f, "the loop doesn't end where it starts"),
+        }
+    }
+}
+
+/// Check that the loop is closed and only turns by 90 degrees, and return the
+/// number of clockwise turns minus the number of counter-clockwise ones.
+fn check_loop(segment_loop: &SegmentLoop) -> Result<isize, LoopError> {
+    if !segment_loop.is_closed() {
+        return Err(LoopError::NotClosed);
+    }
+
     let mut cw_turn_count = 0isize;
     let mut current_direction = segment_loop.steps[0].direction;
-    for step in &segment_loop.steps[1..] {
+    for (index, step) in segment_loop.steps.iter().enumerate().skip(1) {
         let turn = match (current_direction, step.direction) {
             (Direction::Up, Direction::Right) => 1,
             (Direction::Right, Direction::Down) => 1,
@@ -263,14 +349,18 @@ fn exterior_area_of_loop(segment_loop: &SegmentLoop) -> isize {
             (Direction::Down, Direction::Right) => -1,
             (Direction::Right, Direction::Up) => -1,
             (a, b) if a == b => 0,
-            _ => panic!(
-                "only 90 degree turns expected {:?} -> {:?}",
-                current_direction, step.direction
-            ),
+            (from, to) => return Err(LoopError::InvalidTurn { index, from, to }),
         };
         cw_turn_count += turn;
         current_direction = step.direction;
     }
+    Ok(cw_turn_count)
+}
+
+fn exterior_area_of_loop(segment_loop: &SegmentLoop) -> Result<isize, LoopError> {
+    // We figure out which side is inside or outside using the same method as in
+    // day 10, by counting the turns
+    let cw_turn_count = check_loop(segment_loop)?;
 
     let outside = if cw_turn_count > 0 {
         // clockwise
@@ -288,7 +378,51 @@ fn exterior_area_of_loop(segment_loop: &SegmentLoop) -> isize {
         .map(|(step_start, step)| step_start.step(outside(step.direction), 1))
         .collect();
     let area = shoelace_formula(&vertices);
-    area - 1
+    Ok(area - 1)
+}
+
+/// Positions at the start of each step of the loop, starting from the origin.
+/// Unlike [SegmentLoop::positions], the start is not repeated at the end.
+fn vertices(segment_loop: &SegmentLoop) -> Vec<Position> {
+    segment_loop
+        .positions(Position::default())
+        .take(segment_loop.steps.len())
+        .collect()
+}
+
+/// Total length of the loop.
+fn perimeter(segment_loop: &SegmentLoop) -> isize {
+    segment_loop.steps.iter().map(|step| step.distance).sum()
+}
+
+/// Number of cells dug out along the loop. Each unit step moves into one new
+/// cell, and the last one is back at the start, so this is the same as the
+/// [perimeter].
+fn border_cells(segment_loop: &SegmentLoop) -> isize {
+    perimeter(segment_loop)
+}
+
+/// Count the cells strictly inside the loop and the cells on the loop itself.
+///
+/// The loop goes through the centers of the cells, so the area of the polygon
+/// it traces is smaller than the number of cells it encloses. Pick's theorem
+/// relates the polygon area `A` to the number of interior points `i` and
+/// boundary points `b` of the integer grid: `A = i + b/2 - 1`. Cell centers
+/// are the grid points, so `b` is the number of [border_cells].
+fn interior_and_perimeter(segment_loop: &SegmentLoop) -> (isize, isize) {
+    let area = shoelace_formula(&vertices(segment_loop));
+    let border = border_cells(segment_loop);
+    let interior = area - border / 2 + 1;
+    (interior, border)
+}
+
+/// Number of cells dug out by the loop, border included. Instead of inflating
+/// the loop like [exterior_area_of_loop], use Pick's theorem (see
+/// [interior_and_perimeter]): `i + b = A + b/2 + 1`.
+fn lagoon_volume(segment_loop: &SegmentLoop) -> Result<isize, LoopError> {
+    check_loop(segment_loop)?;
+    let (interior, border) = interior_and_perimeter(segment_loop);
+    Ok(interior + border)
 }
 
 #[aoc(day18, part1)]
@@ -298,7 +432,7 @@ fn part1(input: &str) -> usize {
     #[cfg(feature = "draw-visuals")]
     draw_loop_as_svg_path(&segment_loop, "day18-p1.svg");
 
-    exterior_area_of_loop(&segment_loop) as usize
+    exterior_area_of_loop(&segment_loop).unwrap_or_else(|err| panic!("{err}")) as usize
 }
 
 #[aoc(day18, part2)]
@@ -308,13 +442,30 @@ fn part2(input: &str) -> usize {
     #[cfg(feature = "draw-visuals")]
     draw_loop_as_svg_path(&segment_loop, "day18-p2.svg");
 
-    exterior_area_of_loop(&segment_loop) as usize
+    lagoon_volume(&segment_loop).unwrap_or_else(|err| panic!("{err}")) as usize
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXAMPLE: &str = "
+        R 6 (#70c710)
+        D 5 (#0dc571)
+        L 2 (#5713f0)
+        D 2 (#d2c081)
+        R 2 (#59c680)
+        D 2 (#411b91)
+        L 5 (#8ceee2)
+        U 2 (#caa173)
+        L 1 (#1b58a2)
+        U 2 (#caa171)
+        R 2 (#7807d2)
+        U 3 (#a77fa3)
+        L 2 (#015232)
+        U 2 (#7a21e3)
+        ";
+
     #[test]
     fn part2_parser() {
         let input = unindent::unindent(
@@ -366,7 +517,210 @@ mod tests {
     fn part2_simple() {
         // rectangle of interior size 3x3 (4x4 including the border)
         let segment_loop = parse_regular("R 3\nD 3\nL 3\nU 3");
-        assert_eq!(exterior_area_of_loop(&segment_loop), 16);
+        assert_eq!(exterior_area_of_loop(&segment_loop), Ok(16));
+    }
+
+    #[test]
+    fn both_parsers() {
+        let input = unindent::unindent(EXAMPLE);
+        let (regular, alternate) = parse_both(&input);
+        assert_eq!(regular, parse_regular(&input));
+        assert_eq!(alternate, parse_alternate(&input));
+        assert_eq!(perimeter(&regular), 38);
+        assert_eq!(perimeter(&alternate), 6405262);
+    }
+
+    #[test]
+    fn example_vertices() {
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        let vertices = vertices(&segment_loop);
+        assert_eq!(vertices.len(), 14);
+        assert_eq!(vertices[0], Position { x: 0, y: 0 });
+        assert_eq!(vertices[1], Position { x: 0, y: 6 });
+        assert_eq!(vertices[13], Position { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn alternate_parser_errors() {
+        assert_eq!(
+            Step::try_parse_alternate("R 6 (#70c710)"),
+            Ok(Step {
+                direction: Direction::Right,
+                distance: 461937,
+                color: Color::default()
+            })
+        );
+        assert!(Step::try_parse_alternate("R 6 (#xyz)").is_err());
+        assert!(Step::try_parse_alternate("R 6 (#70c7xy)").is_err());
+        assert!(Step::try_parse_alternate("R 6 (#70c714)").is_err());
+        assert!(Step::try_parse_alternate("").is_err());
+    }
+
+    #[test]
+    fn example_perimeter() {
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        assert_eq!(perimeter(&segment_loop), 38);
+        assert_eq!(perimeter(&parse_regular("R 3\nD 3\nL 3\nU 3")), 12);
+    }
+
+    /// Same loop walked the other way around.
+    fn reversed(segment_loop: &SegmentLoop) -> SegmentLoop {
+        SegmentLoop::new(
+            segment_loop
+                .steps
+                .iter()
+                .rev()
+                .map(|step| Step {
+                    direction: step.direction.opposite(),
+                    ..step.clone()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn reversed_loop_area() {
+        let example = unindent::unindent(EXAMPLE);
+        let input = include_str!("../input/2023/day18.txt");
+        for segment_loop in [
+            parse_regular("R 3\nD 3\nL 3\nU 3"),
+            parse_regular(&example),
+            parse_alternate(&example),
+            parse_regular(input),
+            parse_alternate(input),
+        ] {
+            let reversed = reversed(&segment_loop);
+            assert_eq!(
+                exterior_area_of_loop(&reversed),
+                exterior_area_of_loop(&segment_loop)
+            );
+            assert_eq!(
+                interior_and_perimeter(&reversed),
+                interior_and_perimeter(&segment_loop)
+            );
+        }
+    }
+
+    #[test]
+    fn rectangle_dug_cells() {
+        let vertices = [
+            Position { x: 0, y: 0 },
+            Position { x: 0, y: 5 },
+            Position { x: 3, y: 5 },
+            Position { x: 3, y: 0 },
+        ];
+        // the polygon through the cell centers is 3 by 5...
+        assert_eq!(shoelace_formula(&vertices), 15);
+        // ...but it digs 4 rows of 6 cells
+        assert_eq!(dug_cells(&vertices), 24);
+
+        // same rectangle in a different position
+        let vertices = vertices.map(|p| Position {
+            x: p.x - 10,
+            y: p.y + 7,
+        });
+        assert_eq!(dug_cells(&vertices), 24);
+
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        assert_eq!(dug_cells(&super::vertices(&segment_loop)), 62);
+    }
+
+    #[test]
+    fn svg() {
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        let svg = to_svg(&segment_loop);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        assert!(svg.contains("<path d=\"M 0,0 L 0,6 L 5,6 "));
+        assert_eq!(svg.matches("<line").count(), 14);
+    }
+
+    #[test]
+    fn example_border_cells() {
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        assert_eq!(border_cells(&segment_loop), perimeter(&segment_loop));
+        assert_eq!(border_cells(&segment_loop), 38);
+    }
+
+    #[test]
+    fn interior_and_perimeter_simple() {
+        // the loop encloses a polygon of area 3x3, but it goes through 12 cells
+        // which leave out 2x2 cells inside, for a total of 4x4
+        let segment_loop = parse_regular("R 3\nD 3\nL 3\nU 3");
+        let vertices: Vec<_> = segment_loop.positions(Position::default()).collect();
+        assert_eq!(shoelace_formula(&vertices), 9);
+        let (interior, perimeter) = interior_and_perimeter(&segment_loop);
+        assert_eq!(interior, 4);
+        assert_eq!(perimeter, 12);
+        assert_eq!(interior + perimeter, 16);
+        assert_eq!(exterior_area_of_loop(&segment_loop), Ok(16));
+    }
+
+    #[test]
+    fn interior_and_perimeter_example() {
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        let (interior, perimeter) = interior_and_perimeter(&segment_loop);
+        assert_eq!(perimeter, 38);
+        assert_eq!(interior + perimeter, 62);
+
+        let segment_loop = parse_alternate(&unindent::unindent(EXAMPLE));
+        let (interior, perimeter) = interior_and_perimeter(&segment_loop);
+        assert_eq!(interior + perimeter, 952408144115);
+    }
+
+    #[test]
+    fn reversal() {
+        let segment_loop = parse_regular("R 3\nD 2\nU 2\nL 3");
+        let err = exterior_area_of_loop(&segment_loop).unwrap_err();
+        assert_eq!(
+            err,
+            LoopError::InvalidTurn {
+                index: 2,
+                from: Direction::Down,
+                to: Direction::Up
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "only 90 degree turns expected, but step 2 goes Up after Down"
+        );
+    }
+
+    #[test]
+    fn lagoon_volume_checks_the_loop() {
+        let segment_loop = parse_regular(&unindent::unindent(EXAMPLE));
+        assert_eq!(lagoon_volume(&segment_loop), Ok(62));
+
+        let segment_loop = parse_regular("R 3\nD 2\nU 2\nL 3");
+        assert!(matches!(
+            lagoon_volume(&segment_loop),
+            Err(LoopError::InvalidTurn { index: 2, .. })
+        ));
+        let segment_loop = parse_regular("R 3\nD 3\nL 3\nU 2");
+        assert_eq!(lagoon_volume(&segment_loop), Err(LoopError::NotClosed));
+    }
+
+    #[test]
+    fn unclosed_loop() {
+        assert!(parse_regular("R 3\nD 3\nL 3\nU 3").is_closed());
+        let segment_loop = parse_regular("R 3\nD 3\nL 3\nU 2");
+        assert!(!segment_loop.is_closed());
+        assert_eq!(
+            exterior_area_of_loop(&segment_loop),
+            Err(LoopError::NotClosed)
+        );
+    }
+
+    #[test]
+    fn huge_loop() {
+        // coordinates don't fit in i32, and the sum in the shoelace formula
+        // doesn't fit in i64 even if the area does
+        let side = 3_000_000_000isize;
+        let segment_loop = parse_regular(&format!("R {side}\nD {side}\nL {side}\nU {side}"));
+        assert_eq!(
+            exterior_area_of_loop(&segment_loop).unwrap() as i128,
+            (side as i128 + 1) * (side as i128 + 1)
+        );
     }
 }
 
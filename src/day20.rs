@@ -1,7 +1,7 @@
 use std::{
     collections::{HashMap, VecDeque},
     iter::Sum,
-    ops::{Add, Mul},
+    ops::{Add, Mul, Range, Sub},
 };
 
 use aoc_runner_derive::{aoc, aoc_generator};
@@ -23,6 +23,14 @@ impl PulseCounter {
             Pulse::High => self.high += 1,
         }
     }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn saturating_sub(&self, rhs: &Self) -> Self {
+        Self {
+            low: self.low.saturating_sub(rhs.low),
+            high: self.high.saturating_sub(rhs.high),
+        }
+    }
 }
 
 impl Add for PulseCounter {
@@ -36,6 +44,17 @@ impl Add for PulseCounter {
     }
 }
 
+impl Sub for PulseCounter {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            low: self.low - rhs.low,
+            high: self.high - rhs.high,
+        }
+    }
+}
+
 impl Mul<usize> for PulseCounter {
     type Output = Self;
 
@@ -96,11 +115,20 @@ impl Label {
     const BROADCASTER: Self = Self([0; 4]);
 
     fn new(label: &str) -> Self {
+        Self::try_new(label).unwrap()
+    }
+
+    fn try_new(label: &str) -> Result<Self, String> {
         let mut bytes = [0; 4];
         let label_bytes = label.as_bytes();
-        // panics if label is too long
+        if label_bytes.len() > bytes.len() {
+            return Err(format!(
+                "Label should have at most {} chars: {label:?}",
+                bytes.len()
+            ));
+        }
         bytes[..label_bytes.len()].copy_from_slice(label_bytes);
-        Self(bytes)
+        Ok(Self(bytes))
     }
 }
 
@@ -137,7 +165,7 @@ use ModuleType::*;
 
 use crate::{
     testing::{example_tests, known_input_tests},
-    utils::NumberIteratorExt,
+    utils::{NumberExt, NumberIteratorExt},
 };
 
 use self::parsing::Line;
@@ -152,7 +180,10 @@ struct Module {
 #[derive(Debug, Clone)]
 struct WiringConfiguration {
     modules: Vec<Module>,
+    labels: Vec<Label>,
     broadcaster_index: usize,
+    #[cfg_attr(not(test), allow(dead_code))]
+    sink_indices: Vec<usize>,
     mixer_index: Option<usize>,
 }
 
@@ -160,6 +191,7 @@ impl<'a> FromIterator<&'a Line> for WiringConfiguration {
     fn from_iter<T: IntoIterator<Item = &'a Line>>(iter: T) -> Self {
         let mut map = HashMap::new();
         let mut modules = Vec::new();
+        let mut labels = Vec::new();
         let mut connections = Vec::new();
 
         for line in iter {
@@ -186,11 +218,12 @@ impl<'a> FromIterator<&'a Line> for WiringConfiguration {
             };
             map.insert(line.label, modules.len());
             modules.push(module);
+            labels.push(line.label);
         }
 
-        // there could be one sink (if it's not the trivial example) but not
-        // more than one otherwise we don't know how to solve part 2
-        let mut sink_index = None;
+        // any label that is never defined is a sink; there could be none (in
+        // the trivial examples), one (in real inputs) or several
+        let mut sink_indices = Vec::new();
         for (_, outgoing_label) in &connections {
             if !map.contains_key(outgoing_label) {
                 let sink = Module {
@@ -199,9 +232,9 @@ impl<'a> FromIterator<&'a Line> for WiringConfiguration {
                     outgoing: Vec::new(),
                 };
                 map.insert(*outgoing_label, modules.len());
-                debug_assert!(sink_index.is_none());
-                sink_index = Some(modules.len());
+                sink_indices.push(modules.len());
                 modules.push(sink);
+                labels.push(*outgoing_label);
             }
         }
 
@@ -214,17 +247,60 @@ impl<'a> FromIterator<&'a Line> for WiringConfiguration {
 
         let broadcaster_index = map[&Label::BROADCASTER];
         // The "mixer" is the conjunction module that is connected to the sink
-        // (in inputs compliant with part 2)
-        let mixer_index = sink_index.map(|i| modules[i].incoming[0]);
+        // (in inputs compliant with part 2). With more than one sink we can't
+        // guess which one is meant to turn the machine on.
+        let mixer_index = match sink_indices[..] {
+            [sink_index] => Some(modules[sink_index].incoming[0]),
+            _ => None,
+        };
 
         Self {
             modules,
+            labels,
             broadcaster_index,
+            sink_indices,
             mixer_index,
         }
     }
 }
 
+impl WiringConfiguration {
+    /// Build the wiring, taking the module that feeds `target` as the mixer
+    /// instead of guessing it from the unconnected outputs. If there is no
+    /// module labelled `target`, fall back to the guess.
+    fn with_target<'a>(lines: impl IntoIterator<Item = &'a Line>, target: Label) -> Self {
+        let mut config = Self::from_iter(lines);
+        if let Some(target_index) = config.index_of(target) {
+            config.mixer_index = match config.modules[target_index].incoming[..] {
+                [input] => Some(input),
+                _ => None,
+            };
+        }
+        config
+    }
+
+    /// Indices of `index` and of all the modules that can send pulses to it,
+    /// directly or not.
+    fn upstream_of(&self, index: usize) -> Vec<usize> {
+        let mut found = vec![index];
+        let mut queue = vec![index];
+        while let Some(current) = queue.pop() {
+            for &incoming in &self.modules[current].incoming {
+                if !found.contains(&incoming) {
+                    found.push(incoming);
+                    queue.push(incoming);
+                }
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+
+    fn index_of(&self, label: Label) -> Option<usize> {
+        self.labels.iter().position(|&l| l == label)
+    }
+}
+
 mod parsing {
     use std::str::FromStr;
 
@@ -259,7 +335,7 @@ mod parsing {
                 let raw_label = label_part.get(1..).ok_or_else(|| {
                     format!("Label should have at least two chars: {label_part:?}")
                 })?;
-                let label = Label::new(raw_label);
+                let label = Label::try_new(raw_label)?;
                 let module_type = match type_char {
                     '%' => super::ModuleType::FlipFlop,
                     '&' => super::ModuleType::Conjunction,
@@ -271,8 +347,8 @@ mod parsing {
             let connections = connections_part
                 .split(',')
                 .map(str::trim)
-                .map(Label::new)
-                .collect();
+                .map(Label::try_new)
+                .collect::<Result<_, _>>()?;
 
             Ok(Self {
                 module_type,
@@ -300,6 +376,9 @@ impl<'a> Simulator<'a> {
                     memory_map.insert(index, next_address);
                     next_address += 1;
                 }
+                // A conjunction with no inputs gets an empty slice of memory.
+                // It would send a low pulse if it ever received anything, but
+                // nothing can reach it, so it stays silent forever.
                 Conjunction => {
                     memory_map.insert(index, next_address);
                     next_address += module.incoming.len();
@@ -314,14 +393,74 @@ impl<'a> Simulator<'a> {
         }
     }
 
+    /// Current memory of every flip-flop and conjunction, in the order they
+    /// appear in the wiring.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn state_snapshot(&self) -> Vec<(Label, Vec<Pulse>)> {
+        (0..self.wiring.modules.len())
+            .filter_map(|index| {
+                let memory = self.memory[self.memory_range(index)?].to_vec();
+                Some((self.wiring.labels[index], memory))
+            })
+            .collect()
+    }
+
+    /// Where the memory of a module lives, if it has any.
+    fn memory_range(&self, index: usize) -> Option<Range<usize>> {
+        let start_address = *self.memory_map.get(&index)?;
+        let size = match self.wiring.modules[index].module_type {
+            FlipFlop => 1,
+            _ => self.wiring.modules[index].incoming.len(),
+        };
+        Some(start_address..start_address + size)
+    }
+
+    /// Whether every module is in the state it started in.
+    fn is_reset(&self) -> bool {
+        self.memory.iter().all(|&p| p == Pulse::Low)
+    }
+
+    /// Keep pressing the button until every module is back to its initial
+    /// state, and return how many presses that took. Gives up after `max`.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn full_cycle_length(&mut self, max: usize) -> Option<usize> {
+        (1..=max).find(|_| {
+            self.pulse_button();
+            self.is_reset()
+        })
+    }
+
+    /// Press the button and return every `(source, pulse, destination)` in the
+    /// order they are delivered. The button press itself shows up as a pulse
+    /// from the broadcaster to itself.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn trace_button(&mut self) -> Vec<(Label, Pulse, Label)> {
+        let labels = &self.wiring.labels;
+        let mut trace = Vec::new();
+        self.pulse_button_with(|source, pulse, destination| {
+            trace.push((labels[source], pulse, labels[destination]));
+        });
+        trace
+    }
+
     fn pulse_button(&mut self) -> (PulseCounter, Option<usize>) {
-        self.simulate_one_branch(self.wiring.broadcaster_index, Pulse::Low)
+        self.pulse_button_with(|_, _, _| {})
+    }
+
+    /// Like `pulse_button`, but calls `observe(source, pulse, destination)`
+    /// for every pulse delivered during the press.
+    fn pulse_button_with(
+        &mut self,
+        observe: impl FnMut(usize, Pulse, usize),
+    ) -> (PulseCounter, Option<usize>) {
+        self.simulate_one_branch(self.wiring.broadcaster_index, Pulse::Low, observe)
     }
 
     fn simulate_one_branch(
         &mut self,
         input_index: usize,
         input: Pulse,
+        mut observe: impl FnMut(usize, Pulse, usize),
     ) -> (PulseCounter, Option<usize>) {
         let mut counter = PulseCounter::default();
 
@@ -337,6 +476,7 @@ impl<'a> Simulator<'a> {
 
         while let Some((source, pulse, label)) = queue.pop_front() {
             counter.add_pulse(pulse);
+            observe(source, pulse, label);
             let module = &self.wiring.modules[label];
             let new_pulse = match module.module_type {
                 // When it receives a pulse, [the broadcast module] sends the
@@ -397,36 +537,58 @@ impl<'a> Simulator<'a> {
     }
 }
 
-#[allow(dead_code)]
-fn find_cycle(wiring: &WiringConfiguration, max: usize) -> (usize, PulseCounter) {
-    let mut simulator = Simulator::new(wiring);
-
-    let mut counter = PulseCounter::default();
-    for i in 0..max {
-        let (new_counter, _) = simulator.pulse_button();
-        counter = counter + new_counter;
-        if simulator.memory.iter().all(|&p| p == Pulse::Low) {
-            return (i + 1, counter);
-        }
+/// Pulses received by each module over `presses` button presses.
+#[cfg_attr(not(test), allow(dead_code))]
+fn pulse_counts_per_module(
+    config: &WiringConfiguration,
+    presses: usize,
+) -> HashMap<Label, PulseCounter> {
+    let mut counters = vec![PulseCounter::default(); config.modules.len()];
+    let mut simulator = Simulator::new(config);
+    for _ in 0..presses {
+        simulator.pulse_button_with(|_, pulse, destination| {
+            counters[destination].add_pulse(pulse);
+        });
     }
-    (0, counter)
+    config.labels.iter().copied().zip(counters).collect()
 }
 
-#[aoc_generator(day20)]
-fn parse(input: &str) -> Vec<Line> {
+fn try_parse(input: &str) -> Result<Vec<Line>, String> {
     input
         .lines()
         .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .map(|line| line.parse().unwrap())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| {
+            line.parse()
+                .map_err(|err| format!("line {}: {err}", index + 1))
+        })
         .collect()
 }
 
+#[aoc_generator(day20)]
+fn parse(input: &str) -> Vec<Line> {
+    try_parse(input).unwrap_or_else(|err| panic!("{err}"))
+}
+
 #[aoc(day20, part1)]
 fn part1(input: &[Line]) -> PulseCounter {
+    const PRESSES: usize = 1000;
     let config = WiringConfiguration::from_iter(input);
     let mut simulator = Simulator::new(&config);
-    (0..1000).map(|_| simulator.pulse_button().0).sum()
+    let mut counter = PulseCounter::default();
+    for press in 1..=PRESSES {
+        counter = counter + simulator.pulse_button().0;
+        if simulator.is_reset() {
+            // the network completed a full cycle, so the next presses repeat
+            // the same ones: only simulate whatever is left over
+            let remainder: PulseCounter = (0..PRESSES % press)
+                .map(|_| simulator.pulse_button().0)
+                .sum();
+            return counter * (PRESSES / press) + remainder;
+        }
+    }
+    counter
 }
 
 fn completely_ad_hoc_solution_to_part_2(lines: &[Line]) -> usize {
@@ -443,7 +605,15 @@ fn completely_ad_hoc_solution_to_part_2(lines: &[Line]) -> usize {
     // number of clicks before it resets. As an ad hoc solution, we can simply
     // read the network and extract the encoded numbers, then compute the least
     // common multiple. This way we don't have to run any simulation.
+    subnetwork_periods(lines)
+        .into_iter()
+        .least_common_multiple()
+}
 
+/// The number encoded by each subnetwork hanging off the broadcaster, in the
+/// order the broadcaster lists them. See
+/// `completely_ad_hoc_solution_to_part_2` for what the structure looks like.
+fn subnetwork_periods(lines: &[Line]) -> Vec<usize> {
     let map = lines
         .iter()
         .map(|line| (line.label, line))
@@ -459,8 +629,8 @@ fn completely_ad_hoc_solution_to_part_2(lines: &[Line]) -> usize {
             let mut number = 0;
             let mut shift = 0;
             while let Some(current) = next.take() {
-                let line = &map[&current];
-                for connection in line.connections.iter().map(|label| map[&label]) {
+                let line = &map[current];
+                for connection in line.connections.iter().map(|label| map[label]) {
                     if connection.module_type == FlipFlop {
                         next.replace(&connection.label);
                     } else if connection.module_type == Conjunction {
@@ -471,12 +641,12 @@ fn completely_ad_hoc_solution_to_part_2(lines: &[Line]) -> usize {
             }
             number
         })
-        .least_common_multiple()
+        .collect()
 }
 
 #[aoc(day20, part2)]
 fn part2(input: &[Line]) -> usize {
-    let config = WiringConfiguration::from_iter(input);
+    let config = WiringConfiguration::with_target(input, Label::new("rx"));
     // This is less "ad hoc" than part2_ad_hoc because we actually simulate the
     // network, but we are still making a lot of assumptions. In particular, we
     // assume that when the "mixer" is pulsed high, we are at the end of a
@@ -486,7 +656,10 @@ fn part2(input: &[Line]) -> usize {
     // the first click, before any cycle is completed; and funnily enough, the
     // ad hoc solution instead works for the example almost by coincidence.
     let mut simulator = Simulator::new(&config);
-    let inputs_to_sink = config.modules[config.mixer_index.unwrap()].incoming.len();
+    let mixer_index = config
+        .mixer_index
+        .expect("rx must be fed by exactly one module");
+    let inputs_to_sink = config.modules[mixer_index].incoming.len();
     let mut cycle_numbers = Vec::new();
     let mut mask = vec![false; inputs_to_sink];
     let mut click_count = 0;
@@ -508,10 +681,271 @@ fn part2_ad_hoc(input: &[Line]) -> usize {
     completely_ad_hoc_solution_to_part_2(input)
 }
 
+/// Presses at which an input of the mixer sends it a high pulse: `first`, then
+/// every `period` presses after that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    first: usize,
+    period: usize,
+}
+
+impl Cycle {
+    /// An aligned cycle starts at step 0, i.e. it could have been extended
+    /// backwards to press number 0. Only aligned cycles can be combined with a
+    /// plain LCM.
+    fn is_aligned(&self) -> bool {
+        self.first.is_multiple_of(self.period)
+    }
+
+    /// Intersect the presses `offset + k * modulus` with this cycle, returning
+    /// the combined `(offset, modulus)` or `None` if they never meet.
+    fn combine(&self, offset: usize, modulus: usize) -> Option<(usize, usize)> {
+        let residue = self.first % self.period;
+        (0..self.period)
+            .map(|k| offset + k * modulus)
+            .find(|t| t % self.period == residue)
+            .map(|t| (t, modulus.least_common_multiple(self.period)))
+    }
+}
+
+/// How one input of the mixer behaves once its part of the network starts
+/// repeating: the state of every module upstream of the input after `start`
+/// presses shows up again after `start + period` presses, and `high_presses`
+/// are the presses in between during which the input pulses the mixer high.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InputCycle {
+    start: usize,
+    period: usize,
+    high_presses: Vec<usize>,
+}
+
+/// Press the button until the conjunction `mixer` sends out a low pulse and
+/// return the number of presses, or `None` if we can't tell within
+/// `max_presses`.
+///
+/// The behavior of each input of the mixer only depends on the modules
+/// upstream of it. We keep pressing the button and remember the state of
+/// those modules after every press, until each of them repeats a state it
+/// was in before. From then on each input pulses the mixer high on a fixed
+/// set of presses in every period, and we can find the first press where all
+/// of them do it without simulating any further.
+///
+/// That last step assumes that the mixer fires in a press where all of its
+/// inputs pulse it high, which depends on how their pulses interleave. We
+/// check that this holds for every press we simulated, and give up if it
+/// doesn't.
+fn presses_until_mixer_fires(
+    config: &WiringConfiguration,
+    mixer: usize,
+    max_presses: usize,
+) -> Option<usize> {
+    let inputs = &config.modules[mixer].incoming;
+    if inputs.is_empty() {
+        // nothing can ever make it fire
+        return None;
+    }
+    let mut simulator = Simulator::new(config);
+    let upstream: Vec<Vec<Range<usize>>> = inputs
+        .iter()
+        .map(|&input| {
+            config
+                .upstream_of(input)
+                .into_iter()
+                .filter_map(|index| simulator.memory_range(index))
+                .collect()
+        })
+        .collect();
+    let upstream_state = |memory: &[Pulse], ranges: &[Range<usize>]| -> Vec<Pulse> {
+        ranges
+            .iter()
+            .flat_map(|range| memory[range.clone()].iter().copied())
+            .collect()
+    };
+    let mut seen: Vec<HashMap<Vec<Pulse>, usize>> = upstream
+        .iter()
+        .map(|ranges| [(upstream_state(&simulator.memory, ranges), 0)].into())
+        .collect();
+    let mut high_presses = vec![Vec::new(); inputs.len()];
+    let mut cycles = vec![None; inputs.len()];
+
+    for press in 1..=max_presses {
+        let mut fired = false;
+        let mut pulsed_high = vec![false; inputs.len()];
+        simulator.pulse_button_with(|source, pulse, destination| {
+            if source == mixer && pulse == Pulse::Low {
+                fired = true;
+            }
+            if destination == mixer && pulse == Pulse::High {
+                let input = inputs.iter().position(|&i| i == source).unwrap();
+                pulsed_high[input] = true;
+            }
+        });
+        if fired {
+            return Some(press);
+        }
+        if pulsed_high.iter().all(|&high| high) {
+            // the inputs didn't line up after all
+            return None;
+        }
+
+        for (input, ranges) in upstream.iter().enumerate() {
+            if pulsed_high[input] {
+                high_presses[input].push(press);
+            }
+            if cycles[input].is_some() {
+                continue;
+            }
+            let state = upstream_state(&simulator.memory, ranges);
+            if let Some(&start) = seen[input].get(&state) {
+                cycles[input] = Some(InputCycle {
+                    start,
+                    period: press - start,
+                    high_presses: high_presses[input]
+                        .iter()
+                        .copied()
+                        .filter(|&p| p > start)
+                        .collect(),
+                });
+            } else {
+                seen[input].insert(state, press);
+            }
+        }
+
+        if cycles.iter().all(Option::is_some) {
+            let cycles: Vec<InputCycle> = cycles.into_iter().flatten().collect();
+            return first_press_after(&cycles, press);
+        }
+    }
+    None
+}
+
+/// First press after `after` where every input pulses high, given that all of
+/// them are repeating their cycles by then.
+fn first_press_after(cycles: &[InputCycle], after: usize) -> Option<usize> {
+    // Each way to pick one high press per input gives a set of congruences.
+    // Real inputs have exactly one high press per period, so there's only one.
+    let choices = cycles.iter().fold(vec![Vec::new()], |choices, cycle| {
+        choices
+            .iter()
+            .flat_map(|chosen: &Vec<Cycle>| {
+                cycle.high_presses.iter().map(move |&first| {
+                    let mut chosen = chosen.clone();
+                    chosen.push(Cycle {
+                        first,
+                        period: cycle.period,
+                    });
+                    chosen
+                })
+            })
+            .collect()
+    });
+    choices
+        .iter()
+        .filter_map(|chosen| first_common_press(chosen, after + 1))
+        .min()
+}
+
+/// First press, no earlier than `from` and after all cycles have started, that
+/// belongs to all of them.
+fn first_common_press(cycles: &[Cycle], from: usize) -> Option<usize> {
+    let start = cycles.iter().map(|cycle| cycle.first).max()?.max(from);
+    let (offset, modulus) = if cycles.iter().all(Cycle::is_aligned) {
+        (
+            0,
+            cycles
+                .iter()
+                .map(|cycle| cycle.period)
+                .least_common_multiple(),
+        )
+    } else {
+        cycles.iter().try_fold((0, 1), |(offset, modulus), cycle| {
+            cycle.combine(offset, modulus)
+        })?
+    };
+    let skip = start.saturating_sub(offset).div_ceil(modulus);
+    Some(offset + skip * modulus)
+}
+
+/// Number of button presses before the module labelled `target` first emits a
+/// low pulse. If `target` is a sink, which never emits anything, count the
+/// presses before it receives one instead. Returns `None` if there is no such
+/// module or if we can't tell within a reasonable number of presses.
+#[cfg_attr(not(test), allow(dead_code))]
+fn presses_until_low(config: &WiringConfiguration, target: Label) -> Option<usize> {
+    const MAX_PRESSES: usize = 1 << 16;
+    let index = config.index_of(target)?;
+    let module = &config.modules[index];
+    match (module.module_type, &module.incoming[..]) {
+        // conjunctions, like the one feeding `rx`, might take way too many
+        // presses to simulate
+        (Conjunction, _) => presses_until_mixer_fires(config, index, MAX_PRESSES),
+        (Sink, &[input]) if config.modules[input].module_type == Conjunction => {
+            presses_until_mixer_fires(config, input, MAX_PRESSES)
+        }
+        (Sink, _) => {
+            presses_until_low_pulse(config, MAX_PRESSES, |_, destination| destination == index)
+        }
+        _ => presses_until_low_pulse(config, MAX_PRESSES, |source, _| source == index),
+    }
+}
+
+/// Press the button until there's a low pulse from `source` to `destination`
+/// such that `matches(source, destination)`, and return the number of presses.
+fn presses_until_low_pulse(
+    config: &WiringConfiguration,
+    max_presses: usize,
+    matches: impl Fn(usize, usize) -> bool,
+) -> Option<usize> {
+    let mut simulator = Simulator::new(config);
+    (1..=max_presses).find(|_| {
+        let mut found = false;
+        simulator.pulse_button_with(|source, pulse, destination| {
+            found |= pulse == Pulse::Low && matches(source, destination);
+        });
+        found
+    })
+}
+
+#[aoc(day20, part2, general)]
+fn part2_general(input: &[Line]) -> usize {
+    let config = WiringConfiguration::with_target(input, Label::new("rx"));
+    let mixer = config
+        .mixer_index
+        .expect("no module is connected to a sink");
+    presses_until_mixer_fires(&config, mixer, 1 << 16)
+        .expect("inputs to the mixer don't settle into cycles")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn try_parse_missing_arrow() {
+        let err = try_parse(
+            "broadcaster -> a
+            %a -> b
+
+            %b c
+            ",
+        )
+        .unwrap_err();
+        assert!(err.starts_with("line 4:"), "{err}");
+        assert!(err.contains("%b c"), "{err}");
+    }
+
+    #[test]
+    fn try_parse_long_label() {
+        let err = try_parse("%abcde -> a").unwrap_err();
+        assert!(err.starts_with("line 1:"), "{err}");
+        assert!(err.contains("abcde"), "{err}");
+
+        let err = try_parse("broadcaster -> a, abcdef").unwrap_err();
+        assert!(err.contains("abcdef"), "{err}");
+
+        assert!(try_parse("%abcd -> a").is_ok());
+    }
+
     #[test]
     fn part1_simple() {
         let lines = parse(
@@ -567,6 +1001,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn state_snapshot_after_one_press() {
+        let lines = parse(
+            "
+            broadcaster -> a
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> out
+            ",
+        );
+        let wiring = WiringConfiguration::from_iter(&lines);
+        let mut simulator = Simulator::new(&wiring);
+        assert!(simulator
+            .state_snapshot()
+            .iter()
+            .all(|(_, memory)| memory.iter().all(|&p| p == Pulse::Low)));
+
+        simulator.pulse_button();
+        use Pulse::High;
+        assert_eq!(
+            simulator.state_snapshot(),
+            vec![
+                (Label::new("a"), vec![High]),
+                (Label::new("inv"), vec![High]),
+                (Label::new("b"), vec![High]),
+                (Label::new("con"), vec![High, High]),
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_one_press() {
+        let lines = parse(
+            "
+            broadcaster -> a
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> out
+            ",
+        );
+        let wiring = WiringConfiguration::from_iter(&lines);
+        let mut simulator = Simulator::new(&wiring);
+        let trace = simulator.trace_button();
+        let label = Label::new;
+        use Pulse::{High, Low};
+        assert_eq!(
+            trace[..5],
+            [
+                (Label::BROADCASTER, Low, Label::BROADCASTER),
+                (Label::BROADCASTER, Low, label("a")),
+                (label("a"), High, label("inv")),
+                (label("a"), High, label("con")),
+                (label("inv"), Low, label("b")),
+            ]
+        );
+        assert_eq!(trace.last(), Some(&(label("con"), Low, label("out"))));
+        assert_eq!(trace.len(), 8);
+    }
+
     #[test]
     fn part1_cycle() {
         let lines = parse(
@@ -579,9 +1074,77 @@ mod tests {
             ",
         );
         let config = WiringConfiguration::from_iter(&lines);
-        let (n, counter) = find_cycle(&config, 1000);
-        assert_eq!(n, 4);
+        let mut simulator = Simulator::new(&config);
+        assert_eq!(simulator.full_cycle_length(1000), Some(4));
+        let counter: PulseCounter = (0..4).map(|_| simulator.pulse_button().0).sum();
         assert_eq!(counter, PulseCounter { low: 17, high: 11 });
+        assert_eq!(Simulator::new(&config).full_cycle_length(3), None);
+    }
+
+    #[test]
+    fn part1_counts_per_module() {
+        let lines = parse(
+            "broadcaster -> a, b, c
+            %a -> b
+            %b -> c
+            %c -> inv
+            &inv -> a
+            ",
+        );
+        let config = WiringConfiguration::from_iter(&lines);
+        let counts = pulse_counts_per_module(&config, 1000);
+        assert_eq!(
+            counts[&Label::BROADCASTER],
+            PulseCounter { low: 1000, high: 0 }
+        );
+        assert_eq!(
+            counts.values().cloned().sum::<PulseCounter>(),
+            part1(&lines)
+        );
+    }
+
+    #[test]
+    fn conjunction_without_inputs() {
+        let lines = parse(
+            "
+            broadcaster -> a
+            %a -> out
+            &dead -> a, out
+            ",
+        );
+        let config = WiringConfiguration::from_iter(&lines);
+        let dead = config.index_of(Label::new("dead")).unwrap();
+        assert_eq!(config.mixer_index, config.index_of(Label::new("a")));
+
+        let mut simulator = Simulator::new(&config);
+        assert!(simulator
+            .trace_button()
+            .iter()
+            .all(|&(source, _, _)| source != Label::new("dead")));
+        assert!(simulator
+            .state_snapshot()
+            .contains(&(Label::new("dead"), vec![])));
+
+        let without_dead = parse(
+            "
+            broadcaster -> a
+            %a -> out
+            ",
+        );
+        assert_eq!(part1(&lines), part1(&without_dead));
+        assert_eq!(presses_until_mixer_fires(&config, dead, 100), None);
+    }
+
+    #[test]
+    fn pulse_counter_sub() {
+        let a = PulseCounter { low: 8, high: 4 };
+        let b = PulseCounter { low: 4, high: 2 };
+        assert_eq!(a.clone() - b.clone(), PulseCounter { low: 4, high: 2 });
+        assert_eq!(b.saturating_sub(&a), PulseCounter::default());
+        assert_eq!(
+            PulseCounter { low: 5, high: 1 }.saturating_sub(&b),
+            PulseCounter { low: 1, high: 0 }
+        );
     }
 
     #[test]
@@ -597,6 +1160,258 @@ mod tests {
         let result = part1(&lines);
         assert_eq!(result.to_string(), "32000000");
     }
+
+    #[test]
+    fn parse_multiple_sinks() {
+        let lines = parse(
+            "
+            broadcaster -> a, b
+            %a -> out
+            %b -> con
+            &con -> rx, out
+            ",
+        );
+        let config = WiringConfiguration::from_iter(&lines);
+        assert_eq!(config.sink_indices.len(), 2);
+        assert_eq!(config.mixer_index, None);
+        for &sink_index in &config.sink_indices {
+            assert_eq!(config.modules[sink_index].module_type, Sink);
+        }
+        assert_eq!(
+            part1(&lines),
+            PulseCounter {
+                low: 5000,
+                high: 2000
+            }
+        );
+    }
+
+    #[test]
+    fn target_defined_module() {
+        let lines = parse(
+            "
+            broadcaster -> a, b
+            %a -> con
+            %b -> con
+            &con -> rx
+            &rx -> log
+            ",
+        );
+        let guessed = WiringConfiguration::from_iter(&lines);
+        assert_eq!(guessed.mixer_index, guessed.index_of(Label::new("rx")));
+
+        let config = WiringConfiguration::with_target(&lines, Label::new("rx"));
+        assert_eq!(config.mixer_index, config.index_of(Label::new("con")));
+        assert_eq!(part2_general(&lines), 1);
+    }
+
+    #[test]
+    fn part2_target_defined_module() {
+        // `part2` only tracks one mixer input per press, so keep the mixer
+        // down to a single input.
+        let lines = parse(
+            "
+            broadcaster -> a
+            %a -> con
+            &con -> rx
+            &rx -> log
+            ",
+        );
+        assert_eq!(part2(&lines), 1);
+        assert_eq!(part2_general(&lines), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "rx must be fed by exactly one module")]
+    fn part2_without_mixer() {
+        let lines = parse(
+            "
+            broadcaster -> a, b
+            %a -> rx
+            %b -> rx
+            ",
+        );
+        part2(&lines);
+    }
+
+    #[test]
+    fn part2_unaligned_cycles() {
+        // `sa` pulses the mixer at presses 3, 7, 11, ... and `sb` at presses
+        // 7, 15, 23, ... so neither cycle starts at step 0
+        let lines = parse(
+            "
+            broadcaster -> a1, b1
+            %a1 -> a2, ca
+            %a2 -> ca
+            &ca -> sa
+            &sa -> mix
+            %b1 -> b2, cb
+            %b2 -> b3, cb
+            %b3 -> cb
+            &cb -> sb
+            &sb -> mix
+            &mix -> rx
+            ",
+        );
+        let config = WiringConfiguration::from_iter(&lines);
+        let mixer = config.mixer_index.unwrap();
+        let brute_force = presses_by_brute_force(&config, mixer);
+        assert_eq!(brute_force, 7);
+
+        assert_eq!(presses_until_mixer_fires(&config, mixer, 6), None);
+        assert_eq!(part2_general(&lines), brute_force);
+        assert_ne!(part2_ad_hoc(&lines), brute_force);
+    }
+
+    #[test]
+    fn part2_after_the_cycles() {
+        // Two resetting counters like in the real input, but each of them only
+        // counts on odd presses: `ai` pulses the mixer at presses 9, 19, 29, ...
+        // and `bi` at 13, 27, 41, ... Both parts of the network have repeated
+        // a state well before they line up.
+        let lines = parse(
+            "
+            broadcaster -> a, b
+            %a -> ax
+            &ax -> a0
+            %a0 -> a1, ak
+            %a1 -> a2
+            %a2 -> ak
+            &ak -> a0, a1, ai
+            &ai -> mix
+            %b -> bx
+            &bx -> b0
+            %b0 -> b1, bk
+            %b1 -> b2, bk
+            %b2 -> bk
+            &bk -> b0, bi
+            &bi -> mix
+            &mix -> rx
+            ",
+        );
+        let config = WiringConfiguration::from_iter(&lines);
+        let mixer = config.mixer_index.unwrap();
+        let brute_force = presses_by_brute_force(&config, mixer);
+        assert_eq!(brute_force, 69);
+
+        assert_eq!(presses_until_mixer_fires(&config, mixer, 40), Some(69));
+        assert_eq!(presses_until_mixer_fires(&config, mixer, 10), None);
+        assert_eq!(part2_general(&lines), brute_force);
+        assert_ne!(part2_ad_hoc(&lines), brute_force);
+    }
+
+    #[test]
+    fn input_cycles_first_press_after() {
+        let cycle = |start, period, high_presses: &[usize]| InputCycle {
+            start,
+            period,
+            high_presses: high_presses.to_vec(),
+        };
+        assert_eq!(
+            first_press_after(&[cycle(0, 3, &[3]), cycle(0, 5, &[5])], 5),
+            Some(15)
+        );
+        assert_eq!(
+            first_press_after(&[cycle(4, 4, &[7]), cycle(8, 8, &[15])], 16),
+            Some(23)
+        );
+        assert_eq!(
+            first_press_after(&[cycle(0, 6, &[2, 5]), cycle(0, 4, &[4])], 6),
+            Some(8)
+        );
+        assert_eq!(
+            first_press_after(&[cycle(0, 6, &[2, 5]), cycle(0, 4, &[])], 6),
+            None
+        );
+    }
+
+    fn presses_by_brute_force(config: &WiringConfiguration, mixer: usize) -> usize {
+        let mut simulator = Simulator::new(config);
+        (1..)
+            .find(|_| {
+                let mut fired = false;
+                simulator.pulse_button_with(|source, pulse, _| {
+                    fired |= source == mixer && pulse == Pulse::Low;
+                });
+                fired
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn presses_until_low_on_input() {
+        let lines = parse(include_str!("../input/2023/day20.txt"));
+        let config = WiringConfiguration::from_iter(&lines);
+        assert_eq!(
+            presses_until_low(&config, Label::new("rx")),
+            Some(238920142622879)
+        );
+        let mixer_label = config.labels[config.mixer_index.unwrap()];
+        assert_eq!(
+            presses_until_low(&config, mixer_label),
+            Some(238920142622879)
+        );
+    }
+
+    #[test]
+    fn presses_until_low_any_module() {
+        let lines = parse(
+            "
+            broadcaster -> a, c
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> out
+            &c -> f
+            %f -> out
+            ",
+        );
+        let config = WiringConfiguration::from_iter(&lines);
+        let presses = |label| presses_until_low(&config, Label::new(label));
+        assert_eq!(presses_until_low(&config, Label::BROADCASTER), Some(1));
+        assert_eq!(presses("a"), Some(2));
+        assert_eq!(presses("b"), Some(3));
+        assert_eq!(presses("inv"), Some(1));
+        assert_eq!(presses("con"), Some(1));
+        // `out` has two inputs, but `con` is the first to send it a low pulse
+        assert_eq!(presses("out"), Some(1));
+        // `c` keeps sending high pulses, so `f` never does anything
+        assert_eq!(presses("f"), None);
+        assert_eq!(presses("nope"), None);
+    }
+
+    #[test]
+    fn subnetwork_periods_on_input() {
+        let lines = parse(include_str!("../input/2023/day20.txt"));
+        let periods = subnetwork_periods(&lines);
+        assert_eq!(periods.len(), 4);
+        assert!(periods.iter().all(|&period| period < 1 << 12));
+        assert_eq!(periods.into_iter().least_common_multiple(), 238920142622879);
+    }
+
+    #[test]
+    fn cycle_combine() {
+        let cycle = Cycle {
+            first: 7,
+            period: 8,
+        };
+        assert!(!cycle.is_aligned());
+        assert_eq!(cycle.combine(3, 4), Some((7, 8)));
+        assert_eq!(cycle.combine(1, 4), None);
+    }
+
+    #[test]
+    fn cycle_first_common_press() {
+        let cycle = |first, period| Cycle { first, period };
+        assert_eq!(first_common_press(&[cycle(3, 3), cycle(5, 5)], 0), Some(15));
+        assert_eq!(first_common_press(&[cycle(3, 4), cycle(7, 8)], 0), Some(7));
+        assert_eq!(first_common_press(&[cycle(2, 3), cycle(3, 5)], 0), Some(8));
+        assert_eq!(
+            first_common_press(&[cycle(14, 3), cycle(3, 5)], 0),
+            Some(23)
+        );
+        assert_eq!(first_common_press(&[cycle(1, 2), cycle(2, 4)], 0), None);
+    }
 }
 
 example_tests! {
@@ -616,6 +1431,7 @@ example_tests! {
     // part2 => 1, // bad boy part 2
 
     part2_ad_hoc => 1,
+    part2_general => 1,
 }
 
 known_input_tests! {
@@ -623,4 +1439,5 @@ known_input_tests! {
     part1 => super::PulseCounter { low: 16656, high: 42780 },
     part2 => 238920142622879,
     part2_ad_hoc => 238920142622879,
+    part2_general => 238920142622879,
 }
@@ -82,13 +82,13 @@ fn parse_generic<const A: usize, const B: usize>(input: &[u8]) -> Vec<Card<A, B>
     // input file is neatly aligned text so just for fun and because we can let's
     // treat it as a binary file in the most unsafe way
 
-    #[repr(packed)]
+    #[repr(C, packed)]
     #[derive(Clone, Copy)]
     struct Number {
         _fuffa: u8,
         number: AsciiNumber<2>,
     }
-    #[repr(packed)]
+    #[repr(C, packed)]
     struct Line<const A: usize, const B: usize> {
         _card: [u8; 5],
         card_id: AsciiNumber<3>,
@@ -155,7 +155,7 @@ mod tests {
     #[test]
     fn number_unaligned() {
         let data = b"x1234";
-        #[repr(packed)]
+        #[repr(C, packed)]
         struct Foo {
             _foo: u8,
             number: AsciiNumber<4>,
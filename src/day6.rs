@@ -86,7 +86,7 @@ fn part1(input: &[Race]) -> u64 {
     input
         .iter()
         .map(|race| race.press_time_to_beat_record().extent())
-        .fold(1, std::ops::Mul::mul)
+        .product()
 }
 
 fn join_times(races: &[Race]) -> Race {
@@ -23,6 +23,10 @@ where
 }
 
 /// Kinda like Range/RangeInclusive but the end might be > u32::MAX
+///
+/// Intervals are half-open, like [`std::ops::Range`]: they contain `start` and
+/// the `length - 1` values after it, but not `end()`. So `excl(5, 5)` is empty
+/// and doesn't contain 5.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Interval<T = u32> {
     start: T,
@@ -37,6 +41,13 @@ impl<T: Copy> Interval<T> {
     pub(crate) fn len(&self) -> T {
         self.length
     }
+
+    pub(crate) fn is_empty(&self) -> bool
+    where
+        T: Default + PartialEq,
+    {
+        self.length == T::default()
+    }
 }
 
 impl<T: Copy> HasExtent for Interval<T> {
@@ -46,8 +57,17 @@ impl<T: Copy> HasExtent for Interval<T> {
     }
 }
 
+/// Sum of the extents of all `intervals`. Overlapping values are counted more
+/// than once, see [`Interval::merge_all`] to avoid that.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn total_extent<T: Copy + std::iter::Sum>(intervals: &[Interval<T>]) -> T {
+    intervals.iter().map(HasExtent::extent).sum()
+}
+
+// `$wide` must be able to hold `start + length` for any valid interval, so that
+// `end()` doesn't overflow.
 macro_rules! interval_impl {
-    ($t:ty) => {
+    ($t:ty, $wide:ty) => {
         #[allow(dead_code)]
         impl Interval<$t> {
             pub(crate) fn excl(start: $t, end: $t) -> Self {
@@ -55,12 +75,10 @@ macro_rules! interval_impl {
                 (start..end).into()
             }
 
-            pub(crate) fn is_empty(&self) -> bool {
-                self.len() == 0
-            }
-
             pub(crate) fn contains(&self, n: $t) -> bool {
-                n >= self.start && n - self.start < self.length
+                // compare in the wide type: for signed intervals `n - start`
+                // might not fit in `$t`
+                n >= self.start && (n as $wide) < self.end()
             }
 
             pub(crate) fn distance_from_start(&self, n: $t) -> Option<$t> {
@@ -71,34 +89,97 @@ macro_rules! interval_impl {
                 self.start
             }
 
-            pub(crate) fn end(&self) -> u64 {
-                self.start as u64 + self.length as u64
+            pub(crate) fn end(&self) -> $wide {
+                self.start as $wide + self.length as $wide
             }
 
             pub(crate) fn intersection(&self, other: &Self) -> Option<Self> {
-                if self.start >= other.start {
-                    let diff = self.start - other.start;
-                    if other.len() > diff {
-                        let length = self.length.min(other.length - diff);
-                        if length == 0 {
-                            None
-                        } else {
-                            Some(Self::new(self.start, length))
+                // work with the ends in the wide type: for signed intervals the
+                // distance between the two starts might not fit in `$t`
+                let start = self.start.max(other.start);
+                let end = self.end().min(other.end());
+                if (start as $wide) < end {
+                    Some(Self::new(start, (end - start as $wide) as $t))
+                } else {
+                    None
+                }
+            }
+
+            /// Iterate over every value in the interval, in order.
+            pub(crate) fn iter(&self) -> impl Iterator<Item = $t> {
+                let start = self.start;
+                (0..self.length).map(move |offset| start + offset)
+            }
+
+            /// Whether every value in `other` is also in this interval. An
+            /// empty interval is contained in any interval.
+            pub(crate) fn contains_interval(&self, other: &Self) -> bool {
+                other.is_empty() || (other.start >= self.start && other.end() <= self.end())
+            }
+
+            /// Clip this interval to the window `bounds`. Same as
+            /// `intersection`, but reads better when one side is a window.
+            pub(crate) fn clamp_to(&self, bounds: &Self) -> Option<Self> {
+                self.intersection(bounds)
+            }
+
+            /// The same interval, moved `delta` values to the right.
+            pub(crate) fn shift(&self, delta: $t) -> Self {
+                Self::new(self.start + delta, self.length)
+            }
+
+            /// Whether every value in this interval is also in `other`.
+            pub(crate) fn is_subset_of(&self, other: &Self) -> bool {
+                other.contains_interval(self)
+            }
+
+            /// Sort `intervals` and coalesce the ones that overlap or touch, so
+            /// that what's left is disjoint. Empty intervals are dropped.
+            pub(crate) fn merge_all(intervals: &mut Vec<Self>) {
+                intervals.retain(|interval| !interval.is_empty());
+                intervals.sort_by_key(|interval| interval.start);
+                let mut merged: Vec<Self> = Vec::with_capacity(intervals.len());
+                for interval in intervals.drain(..) {
+                    match merged.last_mut() {
+                        Some(last) if interval.start as $wide <= last.end() => {
+                            let end = last.end().max(interval.end());
+                            last.length = (end - last.start as $wide) as $t;
                         }
-                    } else {
-                        None
+                        _ => merged.push(interval),
                     }
-                } else {
-                    other.intersection(self)
                 }
+                *intervals = merged;
+            }
+
+            /// What's left of this interval after removing any overlap with
+            /// `other`: nothing, one interval or two (if `other` cuts through
+            /// the middle).
+            pub(crate) fn subtract(&self, other: &Self) -> Vec<Self> {
+                let Some(overlap) = self.intersection(other) else {
+                    return if self.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![self.clone()]
+                    };
+                };
+                let before = overlap.start - self.start;
+                let after = self.length - before - overlap.length;
+                let mut result = Vec::new();
+                if before > 0 {
+                    result.push(Self::new(self.start, before));
+                }
+                if after > 0 {
+                    result.push(Self::new(overlap.start + overlap.length, after));
+                }
+                result
             }
         }
 
         impl Overlaps for Interval<$t> {
             fn overlaps(&self, other: &Self) -> bool {
-                // I'm too lazy to think how to simplify this so let's just use u64 everywhere
-                let a_start = self.start as u64;
-                let b_start = other.start as u64;
+                // I'm too lazy to think how to simplify this so let's just use the wide type everywhere
+                let a_start = self.start as $wide;
+                let b_start = other.start as $wide;
                 let a_end = self.end();
                 let b_end = other.end();
                 // self.start < other.end && other.start < self.end && !self.is_empty() && !other.is_empty()
@@ -117,8 +198,9 @@ macro_rules! interval_impl {
     };
 }
 
-interval_impl!(u32);
-interval_impl!(u64);
+interval_impl!(u32, u64);
+interval_impl!(u64, u64);
+interval_impl!(i64, i128);
 
 #[cfg(test)]
 mod interval_tests {
@@ -199,6 +281,137 @@ mod interval_tests {
                     );
                 }
 
+                #[test]
+                fn half_open() {
+                    let empty = Interval::excl(5, 5);
+                    assert!(empty.is_empty());
+                    assert_eq!(empty.len(), 0);
+                    assert!(!empty.contains(5));
+                    assert_eq!(empty.distance_from_start(5), None);
+
+                    let single = Interval::excl(5, 6);
+                    assert!(!single.is_empty());
+                    assert_eq!(single.len(), 1);
+                    assert!(single.contains(5));
+                    assert!(!single.contains(6));
+                    assert_eq!(single.end(), 6);
+                    assert_eq!(Interval::from(5..6), single);
+                }
+
+                #[test]
+                fn iter() {
+                    assert_eq!(Interval::excl(3, 6).iter().collect::<Vec<_>>(), [3, 4, 5]);
+                    assert_eq!(Interval::excl(3, 3).iter().count(), 0);
+                    assert_eq!(Interval::new(10, 1000).iter().last(), Some(1009));
+                }
+
+                #[test]
+                fn contains_interval() {
+                    assert!(Interval::excl(10, 50).contains_interval(&Interval::excl(20, 30)));
+                    assert!(Interval::excl(10, 50).contains_interval(&Interval::excl(10, 50)));
+                    assert!(!Interval::excl(10, 50).contains_interval(&Interval::excl(40, 60)));
+                    assert!(!Interval::excl(10, 50).contains_interval(&Interval::excl(0, 20)));
+                    assert!(Interval::excl(10, 50).contains_interval(&Interval::excl(70, 70)));
+                }
+
+                #[test]
+                fn clamp_to() {
+                    let bounds = Interval::excl(10, 50);
+                    assert_eq!(
+                        Interval::excl(0, 20).clamp_to(&bounds),
+                        Some(Interval::excl(10, 20))
+                    );
+                    assert_eq!(
+                        Interval::excl(40, 60).clamp_to(&bounds),
+                        Some(Interval::excl(40, 50))
+                    );
+                    assert_eq!(
+                        Interval::excl(20, 30).clamp_to(&bounds),
+                        Some(Interval::excl(20, 30))
+                    );
+                    assert_eq!(Interval::excl(60, 70).clamp_to(&bounds), None);
+                }
+
+                #[test]
+                fn shift() {
+                    assert_eq!(Interval::excl(10, 20).shift(5), Interval::excl(15, 25));
+                    assert_eq!(Interval::excl(10, 20).shift(0), Interval::excl(10, 20));
+                    assert_eq!(Interval::excl(10, 10).shift(5), Interval::excl(15, 15));
+                }
+
+                #[test]
+                fn is_subset_of() {
+                    let outer = Interval::excl(10, 50);
+                    assert!(outer.is_subset_of(&outer));
+                    assert!(Interval::excl(20, 30).is_subset_of(&outer));
+                    assert!(Interval::excl(10, 30).is_subset_of(&outer));
+                    assert!(!outer.is_subset_of(&Interval::excl(20, 30)));
+                    assert!(!Interval::excl(40, 60).is_subset_of(&outer));
+                    assert!(!Interval::excl(0, 20).is_subset_of(&outer));
+                    assert!(!Interval::excl(60, 70).is_subset_of(&outer));
+                }
+
+                #[test]
+                fn merge_all() {
+                    let mut intervals = vec![
+                        Interval::excl(10, 20),
+                        Interval::excl(15, 25),
+                        Interval::excl(30, 40),
+                    ];
+                    Interval::merge_all(&mut intervals);
+                    assert_eq!(intervals, [Interval::excl(10, 25), Interval::excl(30, 40)]);
+
+                    let mut intervals = vec![
+                        Interval::excl(30, 40),
+                        Interval::excl(5, 8),
+                        Interval::excl(12, 12),
+                        Interval::excl(0, 5),
+                        Interval::excl(32, 35),
+                    ];
+                    Interval::merge_all(&mut intervals);
+                    assert_eq!(intervals, [Interval::excl(0, 8), Interval::excl(30, 40)]);
+
+                    let mut intervals = vec![];
+                    Interval::merge_all(&mut intervals);
+                    assert_eq!(intervals, []);
+                }
+
+                #[test]
+                fn subtract() {
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(20, 30)),
+                        vec![Interval::excl(10, 20), Interval::excl(30, 50)]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(0, 30)),
+                        vec![Interval::excl(30, 50)]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(30, 60)),
+                        vec![Interval::excl(10, 30)]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(10, 50)),
+                        vec![]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(0, 60)),
+                        vec![]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(50, 60)),
+                        vec![Interval::excl(10, 50)]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 50).subtract(&Interval::excl(30, 30)),
+                        vec![Interval::excl(10, 50)]
+                    );
+                    assert_eq!(
+                        Interval::excl(10, 10).subtract(&Interval::excl(0, 5)),
+                        vec![]
+                    );
+                }
+
                 #[test]
                 fn overlaps() {
                     assert!(!Interval::excl(0, 10).overlaps(&Interval::excl(10, 50)));
@@ -223,4 +436,97 @@ mod interval_tests {
 
     test_interval_impl!(u32);
     test_interval_impl!(u64);
+    test_interval_impl!(i64);
+
+    #[test]
+    fn total_extent() {
+        use crate::range::total_extent;
+        type Interval = crate::range::Interval<u64>;
+        let intervals = [
+            Interval::excl(0, 10),
+            Interval::excl(20, 25),
+            Interval::new(1 << 40, 1 << 33),
+        ];
+        assert_eq!(total_extent(&intervals), 15 + (1 << 33));
+        assert_eq!(total_extent::<u64>(&[]), 0);
+    }
+
+    mod signed {
+        use crate::range::Overlaps;
+        type Interval = crate::range::Interval<i64>;
+
+        #[test]
+        fn spanning_zero() {
+            let interval = Interval::excl(-5, 5);
+            assert_eq!(interval.len(), 10);
+            assert_eq!(interval.start(), -5);
+            assert_eq!(interval.end(), 5);
+            assert!(interval.contains(-5));
+            assert!(interval.contains(0));
+            assert!(interval.contains(4));
+            assert!(!interval.contains(5));
+            assert!(!interval.contains(-6));
+            assert_eq!(interval.distance_from_start(-5), Some(0));
+            assert_eq!(interval.distance_from_start(0), Some(5));
+            assert_eq!(interval.distance_from_start(-6), None);
+            assert_eq!(interval.iter().next(), Some(-5));
+            assert_eq!(interval.iter().count(), 10);
+        }
+
+        #[test]
+        fn negative_operations() {
+            let interval = Interval::excl(-5, 5);
+            assert_eq!(
+                interval.intersection(&Interval::excl(-10, -2)),
+                Some(Interval::excl(-5, -2))
+            );
+            assert!(interval.overlaps(&Interval::excl(-10, -4)));
+            assert!(!interval.overlaps(&Interval::excl(-10, -5)));
+            assert_eq!(
+                interval.subtract(&Interval::excl(-1, 1)),
+                vec![Interval::excl(-5, -1), Interval::excl(1, 5)]
+            );
+            assert_eq!(interval.shift(-10), Interval::excl(-15, -5));
+        }
+
+        #[test]
+        fn extremes() {
+            let interval = Interval::new(i64::MIN, i64::MAX);
+            assert_eq!(interval.end(), -1);
+            assert!(interval.contains(i64::MIN));
+            assert!(!interval.contains(i64::MAX));
+            assert!(Interval::new(i64::MAX, 1).contains(i64::MAX));
+            assert_eq!(Interval::new(i64::MAX, 2).end(), i64::MAX as i128 + 2);
+        }
+
+        #[test]
+        fn extremes_intersection() {
+            let high = Interval::new(i64::MAX - 1, 1);
+            assert_eq!(high.intersection(&Interval::new(-2, 1)), None);
+            assert_eq!(Interval::new(-2, 1).intersection(&high), None);
+            assert_eq!(
+                Interval::new(i64::MIN, i64::MAX).intersection(&Interval::new(-2, 10)),
+                Some(Interval::new(-2, 1))
+            );
+            assert_eq!(
+                Interval::new(i64::MIN, 1).intersection(&Interval::new(i64::MIN, i64::MAX)),
+                Some(Interval::new(i64::MIN, 1))
+            );
+            assert_eq!(high.clamp_to(&Interval::new(i64::MIN, 10)), None);
+        }
+
+        #[test]
+        fn extremes_subtract() {
+            let high = Interval::new(i64::MAX - 1, 1);
+            assert_eq!(high.subtract(&Interval::new(-2, 1)), vec![high.clone()]);
+            assert_eq!(
+                Interval::new(i64::MIN, i64::MAX).subtract(&Interval::excl(-10, -5)),
+                vec![Interval::excl(i64::MIN, -10), Interval::excl(-5, -1)]
+            );
+            assert_eq!(
+                Interval::new(-2, 4).subtract(&Interval::new(i64::MIN, i64::MAX)),
+                vec![Interval::excl(-1, 2)]
+            );
+        }
+    }
 }
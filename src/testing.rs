@@ -16,6 +16,7 @@ where
 }
 pub(crate) trait Unindentable {
     type Output: Borrow<Self>;
+    #[cfg_attr(not(test), allow(unused))]
     fn unindent(&self) -> Self::Output;
 }
 
@@ -36,11 +36,11 @@ impl<'a, T> Iterator for PairsIterator<'a, T> {
 }
 
 pub(crate) trait SliceUtils<T> {
-    fn pairs(&self) -> PairsIterator<T>;
+    fn pairs(&self) -> PairsIterator<'_, T>;
 }
 
 impl<T> SliceUtils<T> for [T] {
-    fn pairs(&self) -> PairsIterator<T> {
+    fn pairs(&self) -> PairsIterator<'_, T> {
         PairsIterator::new(self)
     }
 }
@@ -58,6 +58,7 @@ pub(crate) trait AsciiUtils<'a> {
     /// ([rust-lang/rust#94035](https://github.com/rust-lang/rust/issues/94035)).
     fn ascii_trim_end(self) -> Self;
 
+    #[cfg_attr(not(test), allow(unused))]
     fn parse<'f, F>(self) -> Result<F, F::Error>
     where
         F: FromAscii<Slice<'f> = Self>,
@@ -68,17 +69,32 @@ pub(crate) trait AsciiUtils<'a> {
 
     /// Interpret the slice as a grid of cells that can be converted from ASCII
     /// characters, where each line is the same length.
-    fn grid_like<Cell: TryFrom<u8>>(&self) -> Result<GridLike<Cell>, Cell::Error> {
+    ///
+    /// Returns an error if any line has a different length than the first one.
+    fn grid_like<Cell: TryFrom<u8>>(&self) -> Result<GridLike<Cell>, GridError<Cell::Error>> {
         // TODO: probably not optimized
-        let cells = self
-            .ascii_lines()
-            .flat_map(|line| line.iter().map(|&c| c.try_into()))
-            .collect::<Result<Vec<Cell>, Cell::Error>>()?;
         let width = self
             .ascii_lines()
             .next()
             .map(|line| line.len())
             .unwrap_or(0);
+        if let Some((line, len)) = self
+            .ascii_lines()
+            .map(|line| line.len())
+            .enumerate()
+            .find(|&(_, len)| len != width)
+        {
+            return Err(GridError::RaggedLine {
+                line,
+                len,
+                expected: width,
+            });
+        }
+        let cells = self
+            .ascii_lines()
+            .flat_map(|line| line.iter().map(|&c| c.try_into()))
+            .collect::<Result<Vec<Cell>, Cell::Error>>()
+            .map_err(GridError::InvalidCell)?;
         let height = self.ascii_lines().count();
         Ok(GridLike {
             cells,
@@ -143,6 +159,7 @@ impl<'a> Iterator for LinesIterator<'a> {
 }
 
 /// Similar to FromStr, but for ASCII bytes
+#[cfg_attr(not(test), allow(unused))]
 pub(crate) trait FromAscii: Sized {
     type Slice<'a>;
     type Error;
@@ -184,7 +201,21 @@ where
 }
 
 #[derive(Debug)]
-pub(crate) struct InvalidCharacter(pub(crate) u8);
+pub(crate) struct InvalidCharacter(#[allow(dead_code)] pub(crate) u8);
+
+/// Error returned by [AsciiUtils::grid_like]
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) enum GridError<E> {
+    /// A cell could not be converted from its ASCII character
+    InvalidCell(E),
+    /// A line (counting from 0) is not as long as the first one
+    RaggedLine {
+        line: usize,
+        len: usize,
+        expected: usize,
+    },
+}
 
 macro_rules! grid_cell_enum {
     (
@@ -374,4 +405,17 @@ mod tests {
         assert_eq!(grid.height, 4);
         assert_eq!(grid.cells, b"abcdefghijkl".to_vec(),);
     }
+
+    #[test]
+    fn ascii_grid_ragged() {
+        let grid = b"abc\nde\nghi".as_slice().grid_like::<u8>();
+        assert!(matches!(
+            grid,
+            Err(GridError::RaggedLine {
+                line: 1,
+                len: 2,
+                expected: 3
+            })
+        ));
+    }
 }